@@ -0,0 +1,442 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::progress::ProgressTracker;
+use super::settings::{Container, ExportMode, ExportSettings, QualityMode, VideoCodec};
+use super::workspace::Workspace;
+use super::{ExportContext, ExportRequest, ExportTarget};
+use crate::video;
+
+/// Attempts the max-file-size mode makes before giving up
+const MAX_SIZE_ATTEMPTS: usize = 8;
+const MIN_FPS: f64 = 5.0;
+const MIN_WIDTH: u32 = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimatedFormat {
+    Gif,
+    Webp,
+}
+
+/// Dithering used when mapping GIF frames to the palette
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    None,
+    Bayer,
+    FloydSteinberg,
+    #[default]
+    Sierra2_4a,
+}
+
+impl Dither {
+    fn as_str(self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Bayer => "bayer",
+            Dither::FloydSteinberg => "floyd_steinberg",
+            Dither::Sierra2_4a => "sierra2_4a",
+        }
+    }
+}
+
+/// Settings for animated GIF/WebP exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimatedSettings {
+    pub format: AnimatedFormat,
+    #[serde(default = "default_fps")]
+    pub fps: f64,
+    /// Frames wider than this are scaled down; narrower ones are left alone
+    #[serde(default = "default_max_width")]
+    pub max_width: u32,
+    /// How many times the animation plays; 0 loops forever
+    #[serde(default)]
+    pub loop_count: u32,
+    #[serde(default)]
+    pub dither: Dither,
+    /// Bayer pattern scale, 0-5; higher looks less crosshatched but bands more
+    #[serde(default = "default_bayer_scale")]
+    pub bayer_scale: u8,
+    /// WebP quality, 0-100
+    #[serde(default = "default_webp_quality")]
+    pub webp_quality: u8,
+    /// Step fps and width down until the file fits
+    #[serde(default)]
+    pub max_file_size_bytes: Option<u64>,
+}
+
+fn default_fps() -> f64 {
+    15.0
+}
+
+fn default_max_width() -> u32 {
+    640
+}
+
+fn default_bayer_scale() -> u8 {
+    3
+}
+
+fn default_webp_quality() -> u8 {
+    75
+}
+
+impl AnimatedSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1.0..=50.0).contains(&self.fps) {
+            return Err(format!("Animated exports need 1 to 50 fps, got {}", self.fps));
+        }
+        if self.max_width < MIN_WIDTH {
+            return Err(format!("Animated exports need to be at least {}px wide", MIN_WIDTH));
+        }
+        if self.bayer_scale > 5 {
+            return Err("Bayer scale must be between 0 and 5".to_string());
+        }
+        if self.webp_quality > 100 {
+            return Err("WebP quality must be between 0 and 100".to_string());
+        }
+        if self.max_file_size_bytes == Some(0) {
+            return Err("Maximum file size must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            AnimatedFormat::Gif => "gif",
+            AnimatedFormat::Webp => "webp",
+        }
+    }
+
+    /// `-loop` value: the GIF muxer counts repeats after the first play
+    /// (-1 = play once), the WebP muxer counts plays
+    pub(super) fn loop_arg(&self) -> String {
+        match (self.format, self.loop_count) {
+            (_, 0) => "0".to_string(),
+            (AnimatedFormat::Gif, 1) => "-1".to_string(),
+            (AnimatedFormat::Gif, n) => (n - 1).to_string(),
+            (AnimatedFormat::Webp, n) => n.to_string(),
+        }
+    }
+}
+
+/// What an animated export ended up using
+#[derive(Debug, Clone, Serialize)]
+pub struct AnimatedResult {
+    pub fps: f64,
+    pub width: u32,
+    pub size_bytes: u64,
+    pub attempts: usize,
+}
+
+/// Frame rate and width of one encoding attempt
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrameLimits {
+    fps: f64,
+    width: u32,
+}
+
+impl FrameLimits {
+    fn filters(&self) -> String {
+        format!("fps={},scale={}:-2:flags=lanczos", self.fps, self.width)
+    }
+}
+
+/// Two-pass GIF: build a palette from the whole clip, then map every frame to it
+fn encode_gif(
+    input: &Path,
+    settings: &AnimatedSettings,
+    limits: FrameLimits,
+    workspace: &Workspace,
+    output_path: &str,
+    tracker: &Mutex<ProgressTracker>,
+    ctx: &ExportContext,
+) -> Result<(), String> {
+    let frames = limits.filters();
+    let palette = workspace.path("palette.png");
+
+    let mut cmd = FfmpegCommand::new(&palette.to_string_lossy());
+    cmd.input(Input::file(&input.to_string_lossy()));
+    cmd.video_filters(&[format!("{},palettegen=stats_mode=diff", frames)]);
+    ctx.run_stage(&cmd, tracker, 0)
+        .map_err(|e| format!("FFmpeg palettegen failed: {}", e))?;
+
+    let mut dither = format!("dither={}", settings.dither.as_str());
+    if settings.dither == Dither::Bayer {
+        dither.push_str(&format!(":bayer_scale={}", settings.bayer_scale));
+    }
+
+    let mut cmd = FfmpegCommand::new(output_path);
+    cmd.input(Input::file(&input.to_string_lossy()));
+    cmd.input(Input::file(&palette.to_string_lossy()));
+    cmd.filter_complex(&format!(
+        "[0:v]{}[frames];[frames][1:v]paletteuse={}:diff_mode=rectangle[outv]",
+        frames, dither
+    ))
+    .map("[outv]")
+    .output_args(["-loop", &settings.loop_arg()]);
+    ctx.run_stage(&cmd, tracker, 1)
+        .map_err(|e| format!("FFmpeg GIF encode failed: {}", e))
+}
+
+fn encode_webp(
+    input: &Path,
+    settings: &AnimatedSettings,
+    limits: FrameLimits,
+    output_path: &str,
+    tracker: &Mutex<ProgressTracker>,
+    ctx: &ExportContext,
+) -> Result<(), String> {
+    let mut cmd = FfmpegCommand::new(output_path);
+    cmd.input(Input::file(&input.to_string_lossy()));
+    cmd.video_filters(&[limits.filters()]).output_args([
+        "-an".to_string(),
+        "-c:v".to_string(),
+        "libwebp".to_string(),
+        "-quality".to_string(),
+        settings.webp_quality.to_string(),
+        "-compression_level".to_string(),
+        "6".to_string(),
+        "-loop".to_string(),
+        settings.loop_arg(),
+    ]);
+    ctx.run_stage(&cmd, tracker, 0)
+        .map_err(|e| format!("FFmpeg WebP encode failed: {}", e))
+}
+
+/// Limits to try after an output came out too big: scale fps and width down
+/// by the overshoot, but never below the minimums. Every step drops fps or
+/// width by at least one unit; None once neither can drop any further.
+fn step_down(limits: FrameLimits, size: u64, max_size: u64) -> Option<FrameLimits> {
+    // Size scales roughly with fps * width^2, so a cube root spreads the cut over all three
+    let factor = (max_size as f64 / size as f64).clamp(0.3, 0.9).cbrt();
+    let fps = (limits.fps * factor)
+        .round()
+        .min(limits.fps - 1.0)
+        .max(MIN_FPS)
+        .min(limits.fps);
+    let width = ((limits.width as f64 * factor) as u32 & !1)
+        .min(limits.width.saturating_sub(2))
+        .max(MIN_WIDTH)
+        .min(limits.width);
+
+    if fps < limits.fps || width < limits.width {
+        Some(FrameLimits { fps, width })
+    } else {
+        None
+    }
+}
+
+/// Render the timeline to a lossless-looking intermediate, then encode it as
+/// an animated GIF or WebP
+pub fn render(
+    request: &ExportRequest,
+    animated: &AnimatedSettings,
+    workspace: &Workspace,
+    ctx: &ExportContext,
+) -> Result<AnimatedResult, String> {
+    // Everything the timeline does (cuts, transitions, overlays) happens in the intermediate
+    let intermediate = workspace.path("timeline.mp4");
+    let timeline_request = ExportRequest {
+        output_path: intermediate.to_string_lossy().to_string(),
+        mode: ExportMode::Reencode,
+        target: ExportTarget::Video,
+        settings: ExportSettings {
+            video_codec: VideoCodec::H264,
+            quality: QualityMode::Crf { crf: 12 },
+            container: Container::Mp4,
+            fps: None,
+            ..request.settings.clone()
+        },
+        ..request.clone()
+    };
+    super::render(&timeline_request, workspace, ctx)?;
+
+    let source_width = video::probe_streams(&intermediate.to_string_lossy())
+        .map_err(|e| e.message)?
+        .width;
+    let mut limits = FrameLimits {
+        fps: animated.fps,
+        width: source_width.min(animated.max_width) & !1,
+    };
+    let duration = request.total_duration();
+    let mut attempted = limits;
+
+    for attempt in 1..=MAX_SIZE_ATTEMPTS {
+        attempted = limits;
+        let label = format!(
+            "Encoding {} at {}px, {} fps",
+            animated.extension().to_uppercase(),
+            limits.width,
+            limits.fps
+        );
+        println!("🎞️ {} (attempt {})", label, attempt);
+
+        match animated.format {
+            AnimatedFormat::Gif => {
+                let tracker = Mutex::new(ProgressTracker::new(vec![
+                    ("Building palette".to_string(), duration),
+                    (label, duration),
+                ]));
+                encode_gif(&intermediate, animated, limits, workspace, &request.output_path, &tracker, ctx)?
+            }
+            AnimatedFormat::Webp => {
+                let tracker = Mutex::new(ProgressTracker::new(vec![(label, duration)]));
+                encode_webp(&intermediate, animated, limits, &request.output_path, &tracker, ctx)?
+            }
+        }
+
+        let size_bytes = std::fs::metadata(&request.output_path)
+            .map_err(|e| format!("Failed to read output size: {}", e))?
+            .len();
+        let result = AnimatedResult {
+            fps: limits.fps,
+            width: limits.width,
+            size_bytes,
+            attempts: attempt,
+        };
+
+        let Some(max_size) = animated.max_file_size_bytes else { return Ok(result) };
+        if size_bytes <= max_size {
+            return Ok(result);
+        }
+        println!("📉 {} bytes is over the {} byte limit", size_bytes, max_size);
+        match step_down(limits, size_bytes, max_size) {
+            Some(next) => limits = next,
+            None => break,
+        }
+    }
+
+    let _ = std::fs::remove_file(&request.output_path);
+    Err(format!(
+        "Couldn't get the {} under {} bytes even at {}px and {} fps; shorten the timeline or raise the limit",
+        animated.extension().to_uppercase(),
+        animated.max_file_size_bytes.unwrap_or(0),
+        attempted.width,
+        attempted.fps
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::subtitles::SubtitleTrack;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn animated_target_maps_loop_count_and_rejects_subtitle_tracks() {
+        let gif = AnimatedSettings {
+            format: AnimatedFormat::Gif,
+            fps: 15.0,
+            max_width: 480,
+            loop_count: 0,
+            dither: Dither::default(),
+            bayer_scale: 3,
+            webp_quality: 75,
+            max_file_size_bytes: None,
+        };
+        let webp = AnimatedSettings { format: AnimatedFormat::Webp, ..gif.clone() };
+        assert_eq!(gif.loop_arg(), "0");
+        assert_eq!(AnimatedSettings { loop_count: 1, ..gif.clone() }.loop_arg(), "-1");
+        assert_eq!(AnimatedSettings { loop_count: 3, ..gif.clone() }.loop_arg(), "2");
+        assert_eq!(AnimatedSettings { loop_count: 3, ..webp }.loop_arg(), "3");
+
+        let mut request = ExportRequest {
+            clips: vec![clip("a.mp4", 0.0, 2.0)],
+            output_path: "out.gif".to_string(),
+            target: ExportTarget::Animated(gif.clone()),
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+        request.subtitle_tracks.push(SubtitleTrack::default());
+        assert!(request.validate().is_err());
+        request.subtitle_tracks.clear();
+        request.target = ExportTarget::Animated(AnimatedSettings { fps: 0.0, ..gif });
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn gif_export_is_scaled_to_max_width() {
+        let dir = test_dir("gif");
+        let source = make_source(&dir, "src.mp4", 2.0, "640x480", true);
+        let output = dir.join("out.gif").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 2.0)],
+            output_path: output.clone(),
+            target: ExportTarget::Animated(AnimatedSettings {
+                format: AnimatedFormat::Gif,
+                fps: 10.0,
+                max_width: 320,
+                loop_count: 0,
+                dither: Dither::Bayer,
+                bayer_scale: 3,
+                webp_quality: 75,
+                max_file_size_bytes: None,
+            }),
+            ..ExportRequest::default()
+        };
+        let result = export(&request).unwrap().animated.unwrap();
+        assert_eq!(result.width, 320);
+
+        assert_eq!(probe_entries(&output, None, "stream=codec_name,width"), "gif,320");
+    }
+
+    #[test]
+    fn step_down_always_lowers_fps_or_width() {
+        let limits = FrameLimits { fps: 15.0, width: 480 };
+        let next = step_down(limits, 2_000_000, 1_000_000).unwrap();
+        assert!(next.fps < 15.0 && next.width < 480);
+
+        // Barely over the limit: rounding would keep 8 fps, so it drops a frame anyway
+        let next = step_down(FrameLimits { fps: 8.0, width: 200 }, 1_000_001, 1_000_000).unwrap();
+        assert_eq!(next, FrameLimits { fps: 7.0, width: 192 });
+
+        // One side at its minimum: only the other moves
+        let next = step_down(FrameLimits { fps: MIN_FPS, width: 300 }, 4, 1).unwrap();
+        assert_eq!(next.fps, MIN_FPS);
+        assert!(next.width < 300);
+
+        assert_eq!(step_down(FrameLimits { fps: MIN_FPS, width: MIN_WIDTH }, 4, 1), None);
+        // Settings already below the minimums aren't raised to them
+        assert_eq!(step_down(FrameLimits { fps: 3.0, width: 120 }, 4, 1), None);
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn gif_export_steps_down_until_it_fits_the_size_limit() {
+        let dir = test_dir("gif_size");
+        let source = make_source(&dir, "src.mp4", 3.0, "640x480", false);
+        let output = dir.join("out.gif").to_string_lossy().to_string();
+        let settings = AnimatedSettings {
+            format: AnimatedFormat::Gif,
+            fps: 15.0,
+            max_width: 640,
+            loop_count: 0,
+            dither: Dither::default(),
+            bayer_scale: 3,
+            webp_quality: 75,
+            max_file_size_bytes: None,
+        };
+        let mut request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 3.0)],
+            output_path: output.clone(),
+            target: ExportTarget::Animated(settings.clone()),
+            ..ExportRequest::default()
+        };
+        let unlimited = export(&request).unwrap().animated.unwrap();
+
+        // Well under the first attempt's size, so it takes at least one step down
+        let max_size = unlimited.size_bytes / 2;
+        request.target = ExportTarget::Animated(AnimatedSettings { max_file_size_bytes: Some(max_size), ..settings });
+        let result = export(&request).unwrap().animated.unwrap();
+        assert!(result.attempts >= 2);
+        assert!(result.size_bytes <= max_size);
+        assert!(std::fs::metadata(&output).unwrap().len() <= max_size);
+        assert!(result.fps < 15.0 || result.width < 640);
+    }
+}
@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use super::conform;
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::graph;
+use super::mix::{self, MixFormat};
+use super::progress::ProgressTracker;
+use super::{ExportContext, ExportRequest};
+use crate::video::{self, StreamInfo};
+
+/// Sample rates the Opus encoder accepts
+const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+/// Codec and container of an audio-only export
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    Mp3,
+    /// AAC in an MPEG-4 audio file
+    M4a,
+    Wav,
+    Flac,
+    /// Opus in an Ogg file
+    Opus,
+}
+
+impl AudioFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Opus => "opus",
+        }
+    }
+
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "libmp3lame",
+            AudioFormat::M4a => "aac",
+            AudioFormat::Wav => "pcm_s16le",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Opus => "libopus",
+        }
+    }
+
+    /// Lossless formats ignore the bitrate
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, AudioFormat::Wav | AudioFormat::Flac)
+    }
+}
+
+/// Tags written to the output (ID3 for MP3, the container's own tags otherwise).
+/// Unset fields are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub track: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl AudioMetadata {
+    /// `(key, value)` pairs for ffmpeg's `-metadata`
+    fn tags(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("title", &self.title),
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("album_artist", &self.album_artist),
+            ("date", &self.date),
+            ("genre", &self.genre),
+            ("track", &self.track),
+            ("comment", &self.comment),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| (key, v))
+        })
+        .collect()
+    }
+}
+
+/// Settings for audio-only exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioSettings {
+    pub format: AudioFormat,
+    #[serde(default = "default_bitrate")]
+    pub bitrate_kbps: u32,
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+    /// 1 for mono, 2 for stereo
+    #[serde(default = "default_channels")]
+    pub channels: u32,
+    #[serde(default)]
+    pub metadata: AudioMetadata,
+}
+
+fn default_bitrate() -> u32 {
+    192
+}
+
+fn default_sample_rate() -> u32 {
+    48000
+}
+
+fn default_channels() -> u32 {
+    2
+}
+
+impl AudioSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.format.is_lossless() {
+            let max = match self.format {
+                AudioFormat::Mp3 => 320,
+                _ => 512,
+            };
+            if !(8..=max).contains(&self.bitrate_kbps) {
+                return Err(format!(
+                    "{} bitrate must be between 8 and {} kbps, got {}",
+                    self.format.extension().to_uppercase(),
+                    max,
+                    self.bitrate_kbps
+                ));
+            }
+        }
+        if !(8000..=192000).contains(&self.sample_rate) {
+            return Err(format!("Sample rate {} Hz is out of range", self.sample_rate));
+        }
+        if self.format == AudioFormat::Mp3 && self.sample_rate > 48000 {
+            return Err("MP3 supports sample rates up to 48000 Hz".to_string());
+        }
+        if self.format == AudioFormat::Opus && !OPUS_SAMPLE_RATES.contains(&self.sample_rate) {
+            return Err(format!(
+                "Opus needs a sample rate of 8000, 12000, 16000, 24000 or 48000 Hz, got {}",
+                self.sample_rate
+            ));
+        }
+        if !(1..=2).contains(&self.channels) {
+            return Err("Audio exports are mono or stereo".to_string());
+        }
+        Ok(())
+    }
+
+    fn channel_layout(&self) -> &'static str {
+        if self.channels == 1 {
+            "mono"
+        } else {
+            "stereo"
+        }
+    }
+
+    /// Encoder, tag and muxer arguments, placed just before the output path
+    pub fn codec_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-vn".into(), "-c:a".into(), self.format.encoder().into()];
+        if !self.format.is_lossless() {
+            args.extend(["-b:a".into(), format!("{}k", self.bitrate_kbps)]);
+        }
+        args.extend([
+            "-ar".into(),
+            self.sample_rate.to_string(),
+            "-ac".into(),
+            self.channels.to_string(),
+        ]);
+
+        // Only our tags, not whatever the first source file carried
+        args.extend(["-map_metadata".into(), "-1".into()]);
+        for (key, value) in self.metadata.tags() {
+            args.extend(["-metadata".into(), format!("{}={}", key, value)]);
+        }
+
+        match self.format {
+            // ID3v2.3 is what most players and OSes read reliably
+            AudioFormat::Mp3 => args.extend(["-id3v2_version".into(), "3".into()]),
+            AudioFormat::M4a => args.extend(["-movflags".into(), "+faststart".into()]),
+            _ => {}
+        }
+        args
+    }
+}
+
+/// Audio-only `filter_complex` for the timeline, ending in `[outa]`: the
+/// video graph's clip chains and joins without the video. Clips without an
+/// audio stream contribute silence so the timing stays intact.
+fn build_graph(request: &ExportRequest, sources: &[StreamInfo], settings: &AudioSettings) -> String {
+    let clips = &request.clips;
+    let mut chains: Vec<String> = clips
+        .iter()
+        .enumerate()
+        .map(|(i, clip)| {
+            let audio = if sources[i].has_audio {
+                // Every clip is brought to the same rate and layout so concat/acrossfade accept them
+                let format = conform::audio_format_filters(settings.sample_rate, settings.channel_layout());
+                graph::clip_audio_chain(i, clip, &sources[i], format)
+            } else {
+                format!(
+                    "anullsrc=r={}:cl={},atrim=duration={}",
+                    settings.sample_rate,
+                    settings.channel_layout(),
+                    clip.timeline_duration()
+                )
+            };
+            format!("{}[a{}]", audio, i)
+        })
+        .collect();
+    graph::join_clips(clips, false, true, &mut chains);
+    chains.join(";")
+}
+
+/// ffmpeg command that renders the timeline's audio to `request.output_path`
+/// `sources[i]` describes the file behind clip `i`
+pub fn command(request: &ExportRequest, sources: &[StreamInfo], settings: &AudioSettings) -> FfmpegCommand {
+    let mut cmd = FfmpegCommand::new(&request.output_path);
+    for clip in &request.clips {
+        cmd.input(
+            Input::file(&clip.source_path)
+                .seek(clip.in_sec)
+                .duration(clip.source_duration()),
+        );
+    }
+    let mut graph = build_graph(request, sources, settings);
+    let mut label = "[outa]";
+    if mix::needs_mix(request) {
+        let format = MixFormat {
+            sample_rate: settings.sample_rate,
+            channel_layout: settings.channel_layout(),
+        };
+        let (inputs, chains) = mix::mix_chains(request, Some("outa"), format, request.clips.len(), "outmix");
+        for input in inputs {
+            cmd.input(input);
+        }
+        graph.push(';');
+        graph.push_str(&chains.join(";"));
+        label = "[outmix]";
+    }
+    cmd.filter_complex(&graph)
+        .map(label)
+        .output_args(settings.codec_args());
+    cmd
+}
+
+/// Render only the audio of the timeline
+pub fn render(request: &ExportRequest, settings: &AudioSettings, ctx: &ExportContext) -> Result<(), String> {
+    let sources = request
+        .clips
+        .iter()
+        .map(|clip| video::probe_streams(&clip.source_path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.message)?;
+    if !sources.iter().any(|s| s.has_audio) && !request.audio_tracks.iter().any(|t| !t.clips.is_empty()) {
+        return Err("None of the clips have audio to export".to_string());
+    }
+
+    let cmd = command(request, &sources, settings);
+    println!(
+        "🎧 Rendering {} audio ({} clips)",
+        settings.format.extension().to_uppercase(),
+        request.clips.len()
+    );
+    let tracker = Mutex::new(ProgressTracker::new(vec![(
+        "Rendering audio".to_string(),
+        request.total_duration(),
+    )]));
+    ctx.run_stage(&cmd, &tracker, 0)
+        .map_err(|e| format!("FFmpeg audio render failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::transition::TransitionKind;
+    use crate::export::ExportTarget;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn audio_export_fills_silent_clips_and_writes_tags() {
+        let settings = AudioSettings {
+            format: AudioFormat::Mp3,
+            bitrate_kbps: 160,
+            sample_rate: 44100,
+            channels: 2,
+            metadata: AudioMetadata {
+                title: Some("Episode 1".to_string()),
+                artist: Some("  ".to_string()),
+                ..AudioMetadata::default()
+            },
+        };
+        let request = ExportRequest {
+            clips: vec![clip("a.mp4", 0.0, 2.0), clip("b.mp4", 1.0, 2.5)],
+            output_path: "out.mp3".to_string(),
+            target: ExportTarget::Audio(settings.clone()),
+            ..ExportRequest::default()
+        };
+        let sources = [stream(640, 360, 30.0), StreamInfo { has_audio: false, ..stream(640, 360, 30.0) }];
+        let args = command(&request, &sources, &settings).to_args().join(" ");
+
+        assert!(args.contains("[0:a]atrim=duration=2,asetpts=PTS-STARTPTS,aresample=44100"));
+        assert!(args.contains("anullsrc=r=44100:cl=stereo,atrim=duration=1.5[a1]"));
+        assert!(args.contains("[a0][a1]concat=n=2:v=0:a=1[outa]"));
+        assert!(args.contains("-c:a libmp3lame -b:a 160k -ar 44100 -ac 2"));
+        assert!(args.contains("-metadata title=Episode 1"));
+        assert!(!args.contains("artist="));
+        assert!(args.contains("-id3v2_version 3"));
+
+        // Transitions are joined like the video graph's audio, minus the video
+        let faded = ExportRequest {
+            clips: vec![
+                with_transition(clip("a.mp4", 0.0, 2.0), TransitionKind::Crossfade, 0.5),
+                clip("b.mp4", 1.0, 2.5),
+                clip("c.mp4", 0.0, 1.0),
+            ],
+            ..request.clone()
+        };
+        let sources = [sources[0].clone(), sources[1].clone(), sources[0].clone()];
+        let args = command(&faded, &sources, &settings).to_args().join(" ");
+        assert!(args.contains("[a0][a1]acrossfade=d=0.5[aj1];[aj1][a2]concat=n=2:v=0:a=1[outa]"));
+        assert!(!args.contains("xfade=") && !args.contains("[v0]"));
+
+        let opus = AudioSettings { format: AudioFormat::Opus, ..settings };
+        assert!(opus.validate().is_err());
+    }
+
+    #[test]
+    fn audio_export_keeps_variable_frame_rate_sources_in_sync() {
+        let settings = AudioSettings {
+            format: AudioFormat::M4a,
+            bitrate_kbps: 128,
+            sample_rate: 48000,
+            channels: 2,
+            metadata: AudioMetadata::default(),
+        };
+        let request = ExportRequest {
+            clips: vec![clip("screen.webm", 1.0, 3.0), clip("b.mp4", 0.0, 1.0)],
+            output_path: "out.m4a".to_string(),
+            target: ExportTarget::Audio(settings.clone()),
+            ..ExportRequest::default()
+        };
+        let sources = [
+            StreamInfo { variable_frame_rate: true, ..stream(1280, 720, 30.0) },
+            stream(640, 360, 30.0),
+        ];
+        let args = command(&request, &sources, &settings).to_args().join(" ");
+
+        // Stretched to its timestamps; the constant frame rate clip isn't
+        assert!(args.contains("[0:a]atrim=duration=2,asetpts=PTS-STARTPTS,aresample=async=1000,"));
+        assert!(!args.contains("[1:a]aresample=async"));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn audio_only_export_keeps_timeline_duration() {
+        let dir = test_dir("audio_only");
+        let with_audio = make_source(&dir, "voice.mp4", 2.0, "320x240", true);
+        let silent = make_source(&dir, "silent.mp4", 2.0, "320x240", false);
+        let output = dir.join("out.m4a").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&with_audio, 0.0, 2.0), clip(&silent, 0.0, 1.0)],
+            output_path: output.clone(),
+            target: ExportTarget::Audio(AudioSettings {
+                format: AudioFormat::M4a,
+                bitrate_kbps: 128,
+                sample_rate: 48000,
+                channels: 2,
+                metadata: AudioMetadata {
+                    title: Some("Timeline".to_string()),
+                    ..AudioMetadata::default()
+                },
+            }),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert!((probe_duration(&output) - 3.0).abs() < 0.1);
+        let probe = probe_entries(&output, None, "stream=codec_type:format_tags=title");
+        assert!(probe.contains("audio") && !probe.contains("video"));
+        assert!(probe.contains("Timeline"));
+    }
+}
@@ -0,0 +1,312 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::conform::OutputFormat;
+use super::settings::ExportSettings;
+use super::ClipData;
+
+/// Bump when the way segments are rendered changes, so old entries stop matching
+const CACHE_VERSION: u32 = 5;
+
+/// Least recently used segments are evicted once the cache grows past this
+pub const MAX_CACHE_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    /// Read by every export using the cache, written while entries are deleted
+    static ref CACHE_LOCK: RwLock<()> = RwLock::new(());
+}
+
+/// Numbers the temp files of concurrent `store` copies
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Size of the segment cache, returned by `get_export_cache_info`
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheInfo {
+    pub path: String,
+    pub entries: usize,
+    pub total_bytes: u64,
+}
+
+/// Rendered clips kept between exports, keyed by everything that affects
+/// their content so an unchanged clip can be reused as is
+#[derive(Debug)]
+pub struct SegmentCache {
+    dir: PathBuf,
+}
+
+impl SegmentCache {
+    pub fn open() -> Result<Self, String> {
+        Self::open_at(std::env::temp_dir().join("clipforge_segment_cache"))
+    }
+
+    fn open_at(dir: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create segment cache: {}", e))?;
+        Ok(SegmentCache { dir })
+    }
+
+    /// Hold while an export looks up and stores segments, so they aren't
+    /// purged or evicted from under it
+    pub fn in_use(&self) -> RwLockReadGuard<'static, ()> {
+        CACHE_LOCK.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hash of the source file identity (path, mtime, size), the clip (range,
+    /// speed, transform...), the format it's conformed to and the settings
+    /// segments are encoded with. Settings applied to the whole timeline
+    /// afterwards, like loudness, are left out so they don't miss the cache.
+    pub fn key(clip: &ClipData, settings: &ExportSettings, format: &OutputFormat) -> Result<String, String> {
+        let metadata = std::fs::metadata(&clip.source_path)
+            .map_err(|e| format!("Failed to read {}: {}", clip.source_path, e))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let clip_json = serde_json::to_string(clip)
+            .map_err(|e| format!("Failed to serialize clip: {}", e))?;
+        let encoding = (
+            settings.video_codec,
+            &settings.quality,
+            settings.audio_bitrate_kbps,
+            settings.audio_sample_rate,
+            settings.container,
+        );
+        let settings_json = serde_json::to_string(&encoding)
+            .map_err(|e| format!("Failed to serialize export settings: {}", e))?;
+
+        let input = format!(
+            "v{}|{}|{}|{}|{}|{}x{}@{}|{}|{}|{}",
+            CACHE_VERSION,
+            clip.source_path,
+            modified,
+            metadata.len(),
+            clip_json,
+            format.frame.width,
+            format.frame.height,
+            format.fps,
+            format.sample_rate,
+            format.has_audio,
+            settings_json
+        );
+        Ok(format!("{:x}", md5::compute(input)))
+    }
+
+    pub fn entry_path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, extension))
+    }
+
+    /// Cached segment for `key`, if a complete one exists. Its modification
+    /// time is bumped so eviction sees it as recently used.
+    pub fn lookup(&self, key: &str, extension: &str) -> Option<PathBuf> {
+        let path = self.entry_path(key, extension);
+        match std::fs::metadata(&path) {
+            Ok(m) if m.len() > 0 => {
+                let _ = std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|f| f.set_modified(SystemTime::now()));
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+
+    /// Move a freshly rendered segment into the cache and return its new path.
+    /// Segments only ever appear in the cache fully written.
+    pub fn store(&self, key: &str, extension: &str, rendered: &Path) -> Result<PathBuf, String> {
+        let path = self.entry_path(key, extension);
+        if std::fs::rename(rendered, &path).is_err() {
+            // Workspace and cache can be on different filesystems; copy under a
+            // temp name of our own instead, since another export may be storing the same key
+            let partial = self.dir.join(format!(
+                "{}.{}_{}.partial",
+                key,
+                std::process::id(),
+                NEXT_PARTIAL.fetch_add(1, Ordering::SeqCst)
+            ));
+            std::fs::copy(rendered, &partial)
+                .and_then(|_| std::fs::rename(&partial, &path))
+                .map_err(|e| format!("Failed to store cached segment: {}", e))?;
+        }
+        Ok(path)
+    }
+
+    /// Cached segments with their size and last use, oldest first. Copies
+    /// still being stored are left out.
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| Some((e.path(), e.metadata().ok()?)))
+                    .filter(|(path, m)| m.is_file() && path.extension().and_then(|ext| ext.to_str()) != Some("partial"))
+                    .map(|(path, m)| (path, m.len(), m.modified().unwrap_or(UNIX_EPOCH)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        entries.sort_by_key(|(_, _, used)| *used);
+        entries
+    }
+
+    pub fn info(&self) -> CacheInfo {
+        let entries = self.entries();
+        CacheInfo {
+            path: self.dir.to_string_lossy().to_string(),
+            entries: entries.len(),
+            total_bytes: entries.iter().map(|(_, size, _)| size).sum(),
+        }
+    }
+
+    /// Delete the least recently used segments until the cache fits in
+    /// `max_bytes`, and return what was removed. Skipped while an export is
+    /// using the cache; the next one to finish evicts instead.
+    pub fn evict(&self, max_bytes: u64) -> CacheInfo {
+        let mut removed = CacheInfo {
+            path: self.dir.to_string_lossy().to_string(),
+            entries: 0,
+            total_bytes: 0,
+        };
+        let Ok(_lock) = CACHE_LOCK.try_write() else { return removed };
+
+        let entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        for (path, size, _) in entries {
+            if total <= max_bytes {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= size;
+                removed.entries += 1;
+                removed.total_bytes += size;
+            }
+        }
+        if removed.entries > 0 {
+            println!("🧹 Evicted {} cached segments ({} bytes)", removed.entries, removed.total_bytes);
+        }
+        removed
+    }
+
+    /// Delete every cached segment and return what was removed. Refused
+    /// while an export is using the cache.
+    pub fn purge(&self) -> Result<CacheInfo, String> {
+        let _lock = CACHE_LOCK
+            .try_write()
+            .map_err(|_| "An export is using the cache; clear it once the export finishes".to_string())?;
+        let removed = self.info();
+        std::fs::remove_dir_all(&self.dir).map_err(|e| format!("Failed to purge segment cache: {}", e))?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create segment cache: {}", e))?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{settings, ExportRequest};
+    use crate::export::test_support::*;
+
+    #[test]
+    fn segment_cache_key_tracks_clip_and_settings() {
+        let dir = test_dir("cache_key");
+        let source = dir.join("src.mp4");
+        std::fs::write(&source, b"not really a video").unwrap();
+        let source = source.to_string_lossy().to_string();
+        let settings = ExportSettings::default();
+        let format = OutputFormat {
+            frame: settings::Resolution { width: 320, height: 240 },
+            fps: 25.0,
+            sample_rate: 48000,
+            has_audio: true,
+        };
+
+        let key = SegmentCache::key(&clip(&source, 0.0, 1.0), &settings, &format).unwrap();
+        assert_eq!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &settings, &format).unwrap());
+        assert_ne!(key, SegmentCache::key(&clip(&source, 0.0, 1.5), &settings, &format).unwrap());
+        let faster = ClipData { speed: 2.0, ..clip(&source, 0.0, 1.0) };
+        assert_ne!(key, SegmentCache::key(&faster, &settings, &format).unwrap());
+        let bigger = OutputFormat { frame: settings::Resolution { width: 640, height: 480 }, ..format };
+        assert_ne!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &settings, &bigger).unwrap());
+        let smoother = OutputFormat { fps: 60.0, ..format };
+        assert_ne!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &settings, &smoother).unwrap());
+
+        let other_settings = ExportSettings {
+            audio_bitrate_kbps: 192,
+            ..ExportSettings::default()
+        };
+        assert_ne!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &other_settings, &format).unwrap());
+        // Loudness is applied to the joined timeline, not to segments
+        let normalized = ExportSettings {
+            loudness: Some(settings::LoudnessTarget { integrated_lufs: -16.0, true_peak_dbtp: -1.5, loudness_range_lu: 11.0 }),
+            ..ExportSettings::default()
+        };
+        assert_eq!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &normalized, &format).unwrap());
+
+        // Rewriting the source changes its size, which must invalidate the entry
+        std::fs::write(&source, b"a different, longer file").unwrap();
+        assert_ne!(key, SegmentCache::key(&clip(&source, 0.0, 1.0), &settings, &format).unwrap());
+    }
+
+    #[test]
+    fn segment_cache_evicts_least_recently_used_and_guards_purge() {
+        let cache = SegmentCache::open_at(test_dir("cache_evict")).unwrap();
+        let rendered = test_dir("cache_evict_rendered");
+        let day_ago = SystemTime::now() - std::time::Duration::from_secs(86_400);
+        for (i, key) in ["old", "used", "new"].into_iter().enumerate() {
+            let file = rendered.join(key);
+            std::fs::write(&file, vec![0u8; 100]).unwrap();
+            let path = cache.store(key, "mp4", &file).unwrap();
+            let age = std::time::Duration::from_secs(3600 * i as u64);
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(day_ago + age).unwrap();
+        }
+        // Looking an entry up makes it the most recently used
+        assert!(cache.lookup("used", "mp4").is_some());
+
+        let removed = cache.evict(200);
+        assert_eq!((removed.entries, removed.total_bytes), (1, 100));
+        assert!(cache.lookup("old", "mp4").is_none());
+        assert_eq!(cache.evict(200).entries, 0);
+
+        // Evicting the newer of the two leaves the one just used
+        cache.evict(100);
+        assert!(cache.lookup("used", "mp4").is_some());
+        assert!(cache.lookup("new", "mp4").is_none());
+
+        {
+            let _in_use = cache.in_use();
+            assert!(cache.purge().is_err());
+            assert_eq!(cache.evict(0).entries, 0);
+        }
+        // A copy another export is still storing isn't an entry yet
+        std::fs::write(cache.dir.join("used.123_1.partial"), vec![0u8; 100]).unwrap();
+        assert_eq!((cache.info().entries, cache.info().total_bytes), (1, 100));
+        assert_eq!(cache.evict(100).entries, 0);
+        assert_eq!(cache.purge().unwrap().entries, 1);
+        assert_eq!(cache.info().entries, 0);
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn cached_export_reuses_unchanged_clips() {
+        let dir = test_dir("cached");
+        let source = make_source(&dir, "src.mp4", 3.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let mut request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 1.0), clip(&source, 1.0, 2.0)],
+            output_path: output.clone(),
+            use_cache: true,
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+        assert_eq!(export(&request).unwrap().reused_segments, 2);
+
+        // Only the edited clip is rendered again
+        request.clips[1].out_sec = 2.5;
+        assert_eq!(export(&request).unwrap().reused_segments, 1);
+        assert!((probe_duration(&output) - 2.5).abs() < 0.15);
+    }
+}
@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+
+use super::ffmpeg::{CancelToken, FfmpegCommand, Input};
+use super::ClipData;
+use crate::video;
+
+/// Seconds of audio rendered by `render_preview`
+pub const PREVIEW_SEC: f64 = 10.0;
+
+/// Harmonics of the mains frequency notched out, fundamental included
+const HUM_HARMONICS: u32 = 4;
+
+/// Q of each hum notch: narrow enough to leave voices alone, wide enough
+/// for mains frequency drift
+const HUM_NOTCH_Q: f64 = 10.0;
+
+/// Numbers previews so each gets its own file
+static NEXT_PREVIEW: AtomicU64 = AtomicU64::new(1);
+
+/// The preview `render_preview` last returned, deleted once a newer one is ready
+static LAST_PREVIEW: Mutex<Option<String>> = Mutex::new(None);
+
+/// Clears previews left over from earlier runs, once per run
+static CLEAR_OLD_PREVIEWS: Once = Once::new();
+
+/// Cleanup for noisy mic recordings, run on the clip's audio before anything
+/// else touches it. Every option is off by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioCleanup {
+    /// Spectral denoise strength in dB of noise reduction (`afftdn`); 0 is
+    /// off, 12 suits fan noise, above 30 starts to sound watery
+    pub denoise_db: f64,
+    /// Cut rumble below this frequency
+    pub highpass_hz: Option<f64>,
+    /// Cut hiss above this frequency
+    pub lowpass_hz: Option<f64>,
+    /// Mains frequency to notch out with its harmonics: 50 or 60
+    pub hum_hz: Option<u32>,
+    /// De-esser intensity, 0 (off) to 1
+    pub deess: f64,
+}
+
+impl AudioCleanup {
+    /// `what` names the clip in the error, e.g. "Clip 2"
+    pub fn validate(&self, what: &str) -> Result<(), String> {
+        if !(0.0..=97.0).contains(&self.denoise_db) {
+            return Err(format!("{} denoise must be between 0 and 97 dB, got {}", what, self.denoise_db));
+        }
+        for cutoff in [self.highpass_hz, self.lowpass_hz].into_iter().flatten() {
+            if !(10.0..=20000.0).contains(&cutoff) {
+                return Err(format!("{} filter cutoffs must be between 10 and 20000 Hz, got {}", what, cutoff));
+            }
+        }
+        if let (Some(high), Some(low)) = (self.highpass_hz, self.lowpass_hz) {
+            if high >= low {
+                return Err(format!("{} high-pass at {} Hz would cut everything below the {} Hz low-pass", what, high, low));
+            }
+        }
+        if let Some(hum) = self.hum_hz {
+            if hum != 50 && hum != 60 {
+                return Err(format!("{} hum removal needs 50 or 60 Hz mains, got {}", what, hum));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.deess) {
+            return Err(format!("{} de-esser intensity must be between 0 and 1, got {}", what, self.deess));
+        }
+        Ok(())
+    }
+
+    /// Filters in the order they should run: band limits and hum first so
+    /// the denoiser doesn't learn them as part of the noise, de-essing last
+    pub fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if let Some(hz) = self.highpass_hz {
+            filters.push(format!("highpass=f={}", hz));
+        }
+        if let Some(hz) = self.lowpass_hz {
+            filters.push(format!("lowpass=f={}", hz));
+        }
+        if let Some(hum) = self.hum_hz {
+            for harmonic in 1..=HUM_HARMONICS {
+                filters.push(format!("bandreject=f={}:t=q:w={}", hum * harmonic, HUM_NOTCH_Q));
+            }
+        }
+        if self.denoise_db > 0.0 {
+            filters.push(format!("afftdn=nr={}", self.denoise_db));
+        }
+        if self.deess > 0.0 {
+            filters.push(format!("deesser=i={}", self.deess));
+        }
+        filters
+    }
+}
+
+impl ClipData {
+    /// Filters that clean up the clip's audio, before it's retimed
+    pub fn cleanup_filters(&self) -> Vec<String> {
+        // Freeze frames are silent already
+        match (&self.cleanup, self.freeze) {
+            (Some(cleanup), None) => cleanup.filters(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A cleaned sample of a clip, to compare with the source from `start_sec`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CleanupPreview {
+    pub path: String,
+    pub start_sec: f64,
+    pub duration_sec: f64,
+}
+
+/// Render up to ten seconds of the clip's audio from `start_sec` (default:
+/// the clip's start) with its cleanup applied, as a WAV file. Rendering a
+/// preview deletes the previous one once the new one is ready.
+pub fn render_preview(clip: &ClipData, start_sec: Option<f64>) -> Result<CleanupPreview, String> {
+    let cleanup = clip.cleanup.as_ref().ok_or("Clip has no audio cleanup to preview")?;
+    cleanup.validate("Clip")?;
+    if clip.freeze.is_some() {
+        return Err("Freeze frames have no audio to clean up".to_string());
+    }
+    let streams = video::probe_streams(&clip.source_path).map_err(|e| e.message)?;
+    if !streams.has_audio {
+        return Err(format!("{} has no audio", clip.source_path));
+    }
+
+    let start = start_sec.unwrap_or(clip.in_sec).clamp(clip.in_sec, clip.out_sec);
+    let duration = (clip.out_sec - start).min(PREVIEW_SEC);
+    if duration <= 0.0 {
+        return Err("Preview starts at the end of the clip".to_string());
+    }
+
+    // Each preview gets its own name, so the webview doesn't play a cached copy
+    // of the last one and concurrent previews don't write the same file
+    let temp_dir = std::env::temp_dir().join("clipforge_cleanup_preview");
+    CLEAR_OLD_PREVIEWS.call_once(|| {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    });
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Failed to create preview directory: {}", e))?;
+    let output = temp_dir.join(format!(
+        "{}_{}.wav",
+        std::process::id(),
+        NEXT_PREVIEW.fetch_add(1, Ordering::SeqCst)
+    ));
+    let output = output.to_string_lossy().to_string();
+
+    let mut cmd = FfmpegCommand::new(&output);
+    cmd.input(Input::file(&clip.source_path).seek(start).duration(duration));
+    cmd.map("0:a:0");
+    cmd.audio_filters(&cleanup.filters());
+    cmd.output_args(["-c:a", "pcm_s16le"]);
+    if let Err(e) = cmd.run(&CancelToken::default(), |_| {}) {
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    // Only the latest preview is kept
+    let previous = LAST_PREVIEW
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(output.clone());
+    if let Some(previous) = previous {
+        let _ = std::fs::remove_file(previous);
+    }
+
+    println!("🎧 Rendered {:.1}s cleanup preview of {}", duration, clip.source_path);
+    Ok(CleanupPreview {
+        path: output,
+        start_sec: start,
+        duration_sec: duration,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{graph, ExportRequest};
+    use crate::export::retime::FreezeFrame;
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn audio_cleanup_runs_before_retiming() {
+        let cleanup = AudioCleanup {
+            denoise_db: 12.0,
+            highpass_hz: Some(80.0),
+            lowpass_hz: Some(12000.0),
+            hum_hz: Some(60),
+            deess: 0.5,
+        };
+        assert_eq!(
+            cleanup.filters(),
+            vec![
+                "highpass=f=80",
+                "lowpass=f=12000",
+                "bandreject=f=60:t=q:w=10",
+                "bandreject=f=120:t=q:w=10",
+                "bandreject=f=180:t=q:w=10",
+                "bandreject=f=240:t=q:w=10",
+                "afftdn=nr=12",
+                "deesser=i=0.5",
+            ]
+        );
+        assert!(AudioCleanup::default().filters().is_empty());
+
+        let fast = ClipData {
+            speed: 2.0,
+            cleanup: Some(AudioCleanup { denoise_db: 20.0, ..AudioCleanup::default() }),
+            ..clip("a.mp4", 0.0, 4.0)
+        };
+        let source = stream(640, 360, 30.0);
+        let graph = graph::build(std::slice::from_ref(&fast), &[source], &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("asetpts=PTS-STARTPTS,afftdn=nr=20,atempo=2"));
+        // Freeze frames have no audio to clean
+        let frozen = ClipData { freeze: Some(FreezeFrame { hold_sec: 2.0 }), ..fast.clone() };
+        assert!(frozen.cleanup_filters().is_empty());
+
+        let mut request = ExportRequest {
+            clips: vec![fast],
+            output_path: "out.mp4".to_string(),
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+        for bad in [
+            AudioCleanup { hum_hz: Some(55), ..AudioCleanup::default() },
+            AudioCleanup { highpass_hz: Some(500.0), lowpass_hz: Some(400.0), ..AudioCleanup::default() },
+            AudioCleanup { denoise_db: -3.0, ..AudioCleanup::default() },
+            AudioCleanup { deess: 2.0, ..AudioCleanup::default() },
+        ] {
+            request.clips[0].cleanup = Some(bad);
+            assert!(request.validate().is_err());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn cleaned_up_audio_renders_in_exports_and_previews() {
+        let dir = test_dir("cleanup");
+        let source = make_source(&dir, "mic.mp4", 12.0, "320x240", true);
+        let cleanup = AudioCleanup { denoise_db: 12.0, highpass_hz: Some(80.0), hum_hz: Some(50), deess: 0.5, ..AudioCleanup::default() };
+
+        let request = ExportRequest {
+            clips: vec![ClipData { cleanup: Some(cleanup), ..clip(&source, 0.0, 3.0) }],
+            output_path: dir.join("out.mp4").to_string_lossy().to_string(),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+        assert!((probe_duration(&request.output_path) - 3.0).abs() < 0.15);
+
+        // Ten seconds from 1s into the source, capped by the clip's end
+        let long = ClipData { out_sec: 12.0, ..request.clips[0].clone() };
+        let preview = render_preview(&long, Some(1.0)).unwrap();
+        assert_eq!((preview.start_sec, preview.duration_sec), (1.0, 10.0));
+        assert!((probe_duration(&preview.path) - 10.0).abs() < 0.15);
+        let tail = render_preview(&long, Some(9.0)).unwrap();
+        assert!((probe_duration(&tail.path) - 3.0).abs() < 0.15);
+        // The earlier preview is deleted once the next one is ready
+        assert_ne!(preview.path, tail.path);
+        assert!(!std::path::Path::new(&preview.path).exists());
+        assert!(std::path::Path::new(&tail.path).exists());
+    }
+}
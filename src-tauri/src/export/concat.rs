@@ -0,0 +1,207 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::cache::SegmentCache;
+use super::conform::OutputFormat;
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::graph;
+use super::pool;
+use super::progress::ProgressTracker;
+use super::settings::Container;
+use super::transform;
+use super::workspace::Workspace;
+use super::{ExportContext, ExportRequest};
+use crate::video::StreamInfo;
+
+/// Render each clip to its own segment, then concatenate them. Used when the
+/// filter graph can't express the timeline, and for cached exports where
+/// unchanged segments are reused instead of rendered. Returns how many
+/// segments came from the cache. `post_filters` are applied to the joined
+/// timeline.
+pub fn render(
+    request: &ExportRequest,
+    sources: &[StreamInfo],
+    workspace: &Workspace,
+    cache: Option<&SegmentCache>,
+    post_filters: &[String],
+    ctx: &ExportContext,
+) -> Result<usize, String> {
+    let clips = &request.clips;
+    let settings = &request.settings;
+    let extension = settings.container.extension();
+    // Every segment is conformed to this format, so they can be joined without re-encoding
+    let format = OutputFormat::choose(clips, sources, settings);
+
+    // Look up every clip first so the progress stages can say which are reused
+    let keys = match cache {
+        Some(_) => clips
+            .iter()
+            .map(|clip| SegmentCache::key(clip, settings, &format).map(Some))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![None; clips.len()],
+    };
+    let cached: Vec<Option<PathBuf>> = keys
+        .iter()
+        .map(|key| cache.zip(key.as_deref()).and_then(|(c, k)| c.lookup(k, extension)))
+        .collect();
+    let reused = cached.iter().filter(|c| c.is_some()).count();
+
+    // One progress stage per clip, plus the final concat over the whole timeline
+    let mut stages: Vec<(String, f64)> = clips
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let action = if cached[i].is_some() { "Reusing cached clip" } else { "Trimming clip" };
+            (format!("{} {}/{}", action, i + 1, clips.len()), c.timeline_duration())
+        })
+        .collect();
+    stages.push(("Concatenating clips".to_string(), request.total_duration()));
+    let concat_stage = stages.len() - 1;
+    let tracker = Mutex::new(ProgressTracker::new(stages));
+
+    // Step 1: Trim each clip to a temporary file, several clips at a time
+    let workers = pool::worker_count(clips.len() - reused);
+    let threads = pool::threads_per_worker(workers).to_string();
+    println!(
+        "🧵 Trimming {} clips on {} workers ({} reused from cache)",
+        clips.len() - reused,
+        workers,
+        reused
+    );
+
+    let segment_files: Vec<PathBuf> = pool::run_ordered(clips, workers, ctx.cancel, |i, clip| {
+        if let Some(path) = &cached[i] {
+            println!("♻️ Reusing cached clip {}", i);
+            let mut tracker = tracker.lock().unwrap();
+            tracker.complete(i);
+            ctx.report(&tracker, i, None);
+            return Ok(path.clone());
+        }
+
+        let temp_output = workspace.path(&format!("clip_{}.{}", i, extension));
+        println!("✂️ Trimming clip {}: {}s to {}s", i, clip.in_sec, clip.out_sec);
+
+        let mut cmd = FfmpegCommand::new(&temp_output.to_string_lossy());
+        cmd.input(
+            Input::file(&clip.source_path)
+                .seek(clip.in_sec)
+                .duration(clip.source_duration()),
+        );
+        // Scale/fps happen here so every intermediate shares the output format
+        let mut video_filters = clip.retime_video_filters();
+        video_filters.extend(transform::clip_video_filters(clip, &sources[i], format.frame));
+        video_filters.extend(format.video_filters());
+        cmd.video_filters(&video_filters);
+        if sources[i].has_audio {
+            let mut audio_filters = graph::clip_audio_filters(clip, &sources[i]);
+            audio_filters.extend(format.audio_filters());
+            cmd.audio_filters(&audio_filters);
+        } else if format.has_audio {
+            // The concat demuxer takes its streams from the first segment, so
+            // video-only clips get a silent track
+            cmd.input(Input::file(&format.silence(clip.timeline_duration())).format("lavfi"));
+            cmd.map("0:v:0").map("1:a:0");
+        }
+        cmd.output_args(settings.codec_args())
+            .output_args(["-threads", &threads]);
+        if matches!(settings.container, Container::Mp4 | Container::Mov) {
+            // One track time base for every segment, or the joined timestamps drift
+            cmd.output_args(["-video_track_timescale", "90000"]);
+        }
+
+        ctx.run_stage(&cmd, &tracker, i)
+            .map_err(|e| format!("FFmpeg trim failed for clip {}: {}", i, e))?;
+
+        match (cache, &keys[i]) {
+            (Some(cache), Some(key)) => cache.store(key, extension, &temp_output),
+            _ => Ok(temp_output),
+        }
+    })?;
+
+    // Step 2: Create concat file list
+    let concat_list = workspace.write_concat_list("concat_list.txt", &segment_files)?;
+    println!("📋 Concat list created with {} files", segment_files.len());
+
+    // Step 3: Concatenate all segments
+    let mut cmd = FfmpegCommand::new(&request.output_path);
+    cmd.input(
+        Input::file(&concat_list.to_string_lossy())
+            .format("concat")
+            .option("-safe", "0"),
+    );
+    // Overlays are drawn on the joined timeline, which means re-encoding it
+    if post_filters.is_empty() {
+        println!("🔗 Concatenating clips without re-encoding...");
+        cmd.output_args(["-c", "copy"]);
+        if matches!(settings.container, Container::Mp4 | Container::Mov) {
+            cmd.output_args(["-movflags", "+faststart"]);
+        }
+    } else {
+        println!("🔗 Concatenating clips with re-encoding for compatibility...");
+        cmd.video_filters(post_filters).output_args(settings.codec_args());
+    }
+
+    ctx.run_stage(&cmd, &tracker, concat_stage)
+        .map_err(|e| format!("FFmpeg concat failed: {}", e))?;
+
+    println!("✅ Concat completed successfully");
+    Ok(reused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video;
+    use crate::export::ffmpeg::CancelToken;
+    use crate::export::test_support::*;
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn trim_concat_fallback_renders_every_clip() {
+        let dir = test_dir("fallback");
+        let source = make_source(&dir, "src.mp4", 3.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 1.0), clip(&source, 1.0, 2.0)],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        let sources = vec![video::probe_streams(&source).unwrap(); 2];
+        let workspace = Workspace::create().unwrap();
+        let cancel = CancelToken::default();
+        let ctx = ExportContext { cancel: &cancel, on_progress: &|_| {} };
+        render(&request, &sources, &workspace, None, &[], &ctx).unwrap();
+
+        assert!((probe_duration(&output) - 2.0).abs() < 0.15);
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn trim_concat_fallback_conforms_mixed_sources() {
+        let dir = test_dir("conform");
+        let with_audio = make_source(&dir, "a.mp4", 2.0, "320x240", true);
+        let silent = make_source(&dir, "b.mp4", 2.0, "640x360", false);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&silent, 0.0, 1.0), clip(&with_audio, 0.0, 1.0)],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        let sources = vec![
+            video::probe_streams(&silent).unwrap(),
+            video::probe_streams(&with_audio).unwrap(),
+        ];
+        let workspace = Workspace::create().unwrap();
+        let cancel = CancelToken::default();
+        let ctx = ExportContext { cancel: &cancel, on_progress: &|_| {} };
+        render(&request, &sources, &workspace, None, &[], &ctx).unwrap();
+
+        // The first segment's silent track keeps the second clip's audio in the join
+        let info = video::probe_streams(&output).unwrap();
+        assert!(info.has_audio);
+        assert_eq!((info.width, info.height), (640, 360));
+        assert!((probe_duration(&output) - 2.0).abs() < 0.15);
+    }
+}
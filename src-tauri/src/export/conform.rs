@@ -0,0 +1,154 @@
+use super::settings::{ExportSettings, Resolution};
+use super::transform;
+use super::ClipData;
+use crate::video::StreamInfo;
+
+/// Highest frame rate picked from the sources when the export doesn't set one
+pub const MAX_AUTO_FPS: f64 = 60.0;
+
+/// The one format every clip is conformed to before the timeline is joined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputFormat {
+    pub frame: Resolution,
+    pub fps: f64,
+    pub sample_rate: u32,
+    /// Whether the timeline has an audio track at all
+    pub has_audio: bool,
+}
+
+impl OutputFormat {
+    /// Pick the output format for the timeline:
+    /// - frame: the export resolution, else the first clip's size after its transform
+    /// - frame rate: the export fps, else the highest source rate, capped at 60
+    /// - pixels: yuv420p with square pixels
+    /// - audio: stereo at the export sample rate, whenever any clip has audio.
+    ///   Clips without audio get silence so every segment carries the same streams.
+    pub fn choose(clips: &[ClipData], sources: &[StreamInfo], settings: &ExportSettings) -> Self {
+        let fps = settings.fps.unwrap_or_else(|| {
+            sources
+                .iter()
+                .map(|s| s.frame_rate)
+                .filter(|fps| fps.is_finite() && *fps > 0.0)
+                .fold(0.0, f64::max)
+                .min(MAX_AUTO_FPS)
+        });
+        OutputFormat {
+            frame: transform::output_frame(clips, sources, settings),
+            fps: if fps > 0.0 { fps } else { 30.0 },
+            sample_rate: settings.audio_sample_rate,
+            has_audio: sources.iter().any(|s| s.has_audio),
+        }
+    }
+
+    /// Filters that bring a clip's video to the output rate, pixel format and
+    /// time base, after it's been fitted to the frame. `fps` also turns
+    /// variable frame rate sources into constant ones.
+    pub fn video_filters(&self) -> Vec<String> {
+        vec![
+            format!("fps={}", self.fps),
+            "format=yuv420p".to_string(),
+            "settb=AVTB".to_string(),
+        ]
+    }
+
+    /// Filters that bring a clip's audio to the output rate and layout
+    pub fn audio_filters(&self) -> Vec<String> {
+        audio_format_filters(self.sample_rate, "stereo")
+    }
+
+    /// Silent stand-in for the audio of a clip that has none
+    pub fn silence(&self, duration_sec: f64) -> String {
+        format!(
+            "anullsrc=r={}:cl=stereo,atrim=duration={}",
+            self.sample_rate, duration_sec
+        )
+    }
+}
+
+/// Filters that pin a clip's audio to its timestamps before anything else
+/// touches it. Variable frame rate recordings drift when played back sample
+/// by sample, so their audio is stretched or padded to match.
+pub fn audio_sync_filters(source: &StreamInfo) -> Vec<String> {
+    if source.variable_frame_rate {
+        vec!["aresample=async=1000".to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// `aresample`/`aformat` pair that converts audio to one rate and channel layout
+pub fn audio_format_filters(sample_rate: u32, channel_layout: &str) -> Vec<String> {
+    vec![
+        format!("aresample={}", sample_rate),
+        format!(
+            "aformat=sample_rates={}:channel_layouts={}",
+            sample_rate, channel_layout
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video;
+    use crate::export::{graph, settings};
+    use crate::export::test_support::*;
+
+    #[test]
+    fn mixed_sources_are_conformed_to_one_format() {
+        let clips = vec![clip("a.mp4", 0.0, 1.0), clip("b.mp4", 0.0, 2.0), clip("c.mp4", 0.0, 1.0)];
+        let sources = vec![
+            stream(1920, 1080, 30.0),
+            StreamInfo { has_audio: false, ..stream(1280, 720, 59.94) },
+            stream(1920, 1080, 120.0),
+        ];
+        // Highest source rate wins, capped at 60; audio is kept since some clips have it
+        let format = OutputFormat::choose(&clips, &sources, &ExportSettings::default());
+        assert_eq!(format.frame, settings::Resolution { width: 1920, height: 1080 });
+        assert_eq!(format.fps, 60.0);
+        assert!(format.has_audio);
+
+        let fixed = ExportSettings { fps: Some(24.0), ..ExportSettings::default() };
+        assert_eq!(OutputFormat::choose(&clips, &sources, &fixed).fps, 24.0);
+
+        // The video-only clip gets silence instead of failing the graph
+        let graph = graph::build(&clips, &sources, &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("anullsrc=r=48000:cl=stereo,atrim=duration=2[a1]"));
+        assert!(graph.filter_complex.contains(
+            "[2:a]atrim=duration=1,asetpts=PTS-STARTPTS,aresample=48000,aformat=sample_rates=48000:channel_layouts=stereo[a2]"
+        ));
+        assert!(graph.filter_complex.contains("setsar=1,fps=60,format=yuv420p,settb=AVTB[v1]"));
+        assert!(graph.filter_complex.contains("concat=n=3:v=1:a=1[vcat][outa]"));
+    }
+
+    #[test]
+    fn variable_frame_rate_sources_are_detected_and_resynced() {
+        // Steady 30fps, shuffled like B-frames in decode order
+        let mut steady: Vec<f64> = (0..60).map(|i| i as f64 / 30.0).collect();
+        steady.swap(3, 4);
+        assert!(!video::has_irregular_intervals(&steady));
+        // A screen recording that stalls whenever nothing moves
+        let mut t = 0.0;
+        let stalling: Vec<f64> = (0..60)
+            .map(|i| {
+                t += if i % 5 == 0 { 0.2 } else { 1.0 / 30.0 };
+                t
+            })
+            .collect();
+        assert!(video::has_irregular_intervals(&stalling));
+        assert!(!video::has_irregular_intervals(&stalling[..5]));
+
+        let clips = vec![clip("screen.webm", 0.0, 10.0)];
+        let sources = vec![StreamInfo {
+            has_video: true,
+            has_audio: true,
+            width: 1280,
+            height: 720,
+            frame_rate: 24.6,
+            variable_frame_rate: true,
+        }];
+        let graph = graph::build(&clips, &sources, &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("setpts=PTS-STARTPTS,setsar=1,fps=24.6,format=yuv420p"));
+        assert!(graph.filter_complex.contains("asetpts=PTS-STARTPTS,aresample=async=1000,aresample=48000"));
+    }
+}
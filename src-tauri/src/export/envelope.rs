@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use super::text::escape_filter_value;
+use super::tracks::{AudioClip, MAX_GAIN_DB, MIN_GAIN_DB};
+use super::ClipData;
+
+/// How the gain moves between two keyframes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight line in dB
+    #[default]
+    Linear,
+    /// Eases in and out of each keyframe (smoothstep)
+    Smooth,
+}
+
+/// Gain at a moment of the source file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time_sec: f64,
+    pub gain_db: f64,
+}
+
+/// Gain that changes over a clip. Keyframes are in source time, so they stay
+/// on the same sound when the clip is trimmed or sped up. Before the first
+/// and after the last keyframe the gain holds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeEnvelope {
+    pub keyframes: Vec<Keyframe>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+impl VolumeEnvelope {
+    /// `what` names the clip in the error, e.g. "Clip 2"
+    pub fn validate(&self, what: &str) -> Result<(), String> {
+        if self.keyframes.is_empty() {
+            return Err(format!("{} has a volume envelope without keyframes", what));
+        }
+        for keyframe in &self.keyframes {
+            if !keyframe.time_sec.is_finite() || keyframe.time_sec < 0.0 {
+                return Err(format!("{} has a volume keyframe at an invalid time", what));
+            }
+            if !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&keyframe.gain_db) {
+                return Err(format!(
+                    "{} volume keyframes must be between {} and {} dB, got {}",
+                    what, MIN_GAIN_DB, MAX_GAIN_DB, keyframe.gain_db
+                ));
+            }
+        }
+        if self.keyframes.windows(2).any(|w| w[1].time_sec <= w[0].time_sec) {
+            return Err(format!("{} volume keyframes must be in time order, one per moment", what));
+        }
+        Ok(())
+    }
+
+    /// `volume` filter that follows the envelope. `to_clip_time` maps a source
+    /// time to the clip's own time after trimming and retiming; it may return
+    /// times outside the clip, which still shape the gain at its edges.
+    pub fn volume_filter(&self, to_clip_time: impl Fn(f64) -> f64) -> String {
+        let mut points: Vec<(f64, f64)> = self
+            .keyframes
+            .iter()
+            .map(|k| (to_clip_time(k.time_sec), k.gain_db))
+            .collect();
+        // Reversed clips meet their keyframes backwards
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let expression = format!("pow(10,({})/20)", gain_expression(&points, self.interpolation));
+        format!("volume={}:eval=frame", escape_filter_value(&expression))
+    }
+}
+
+/// Piecewise gain in dB over `t`, as nested ifs: hold, then one segment per
+/// pair of keyframes, then hold
+fn gain_expression(points: &[(f64, f64)], interpolation: Interpolation) -> String {
+    let (first_t, first_db) = points[0];
+    let (_, last_db) = points[points.len() - 1];
+
+    let mut expression = last_db.to_string();
+    for pair in points.windows(2).rev() {
+        let ((t0, db0), (t1, db1)) = (pair[0], pair[1]);
+        let progress = format!("(t-({}))/{}", t0, t1 - t0);
+        let shaped = match interpolation {
+            Interpolation::Linear => progress,
+            Interpolation::Smooth => format!("{p}*{p}*(3-2*{p})", p = progress),
+        };
+        expression = format!("if(lt(t,{}),{}+({})*{},{})", t1, db0, db1 - db0, shaped, expression);
+    }
+    format!("if(lt(t,{}),{},{})", first_t, first_db, expression)
+}
+
+impl ClipData {
+    /// Filters that apply the clip's volume envelope, after it's trimmed and retimed
+    pub fn envelope_filters(&self) -> Vec<String> {
+        // Freeze frames are silent already
+        match (&self.volume, self.freeze) {
+            (Some(envelope), None) => vec![envelope.volume_filter(|t| self.source_offset_to_clip_time(t))],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl AudioClip {
+    /// Filters that apply the clip's volume envelope, after it's trimmed
+    pub fn envelope_filters(&self) -> Vec<String> {
+        match &self.volume {
+            Some(envelope) => vec![envelope.volume_filter(|t| t - self.in_sec)],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{graph, ExportRequest};
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn volume_envelopes_follow_trims_and_speed_changes() {
+        use {Interpolation, Keyframe};
+
+        let dip = VolumeEnvelope {
+            keyframes: vec![
+                Keyframe { time_sec: 12.0, gain_db: 0.0 },
+                Keyframe { time_sec: 16.0, gain_db: -20.0 },
+            ],
+            interpolation: Interpolation::Linear,
+        };
+        // Keyframes are in source time: 12s and 16s land 1s and 3s into a 2x clip from 10s
+        let fast = ClipData { speed: 2.0, volume: Some(dip.clone()), ..clip("a.mp4", 10.0, 20.0) };
+        assert_eq!(
+            fast.envelope_filters(),
+            vec!["volume=pow(10\\,(if(lt(t\\,1)\\,0\\,if(lt(t\\,3)\\,0+(-20)*(t-(1))/2\\,-20)))/20):eval=frame"]
+        );
+
+        // Reversed, the dip plays backwards: -20 dB at 2s rising to 0 dB at 4s
+        let reversed = ClipData { reverse: true, ..fast.clone() };
+        assert!(reversed.envelope_filters()[0].contains("if(lt(t\\,2)\\,-20\\,if(lt(t\\,4)\\,-20+(20)*(t-(2))/2\\,0))"));
+
+        // Trimmed past the first keyframe, the ramp starts before the clip does
+        let trimmed = ClipData { in_sec: 14.0, speed: 1.0, ..fast.clone() };
+        assert!(trimmed.envelope_filters()[0].contains("if(lt(t\\,-2)\\,0\\,if(lt(t\\,2)\\,0+(-20)*(t-(-2))/4"));
+
+        let smooth = VolumeEnvelope { interpolation: Interpolation::Smooth, ..dip.clone() };
+        let eased = ClipData { volume: Some(smooth), ..fast.clone() };
+        assert!(eased.envelope_filters()[0].contains("(t-(1))/2*(t-(1))/2*(3-2*(t-(1))/2)"));
+
+        let source = stream(640, 360, 30.0);
+        let graph = graph::build(std::slice::from_ref(&fast), &[source], &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("atempo=2,volume=pow(10"));
+
+        let mut request = ExportRequest {
+            clips: vec![fast],
+            output_path: "out.mp4".to_string(),
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+        request.clips[0].volume.as_mut().unwrap().keyframes.reverse();
+        assert!(request.validate().is_err());
+        request.clips[0].volume = Some(VolumeEnvelope { keyframes: vec![], ..dip.clone() });
+        assert!(request.validate().is_err());
+        request.clips[0].volume = Some(VolumeEnvelope {
+            keyframes: vec![Keyframe { time_sec: 12.0, gain_db: 40.0 }],
+            ..dip
+        });
+        assert!(request.validate().is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Lets another thread stop the ffmpeg processes an export is currently running
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    aborted: AtomicBool,
+    next_child: AtomicU64,
+    children: Mutex<HashMap<u64, Child>>,
+}
+
+impl CancelToken {
+    /// Mark as cancelled and kill the running ffmpeg processes, if any
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.kill_children();
+    }
+
+    /// Stop all running and future ffmpeg processes because part of the export
+    /// failed. Unlike `cancel`, the export is not reported as cancelled.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.kill_children();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn should_stop(&self) -> Option<&'static str> {
+        if self.is_cancelled() {
+            Some("Export cancelled")
+        } else if self.aborted.load(Ordering::SeqCst) {
+            Some("Export aborted")
+        } else {
+            None
+        }
+    }
+
+    fn kill_children(&self) {
+        for child in self.children.lock().unwrap().values_mut() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Latest values reported by ffmpeg's `-progress` output
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegProgress {
+    pub out_time_sec: f64,
+    pub speed: Option<f64>,
+    pub fps: Option<f64>,
+    pub finished: bool,
+}
+
+impl FfmpegProgress {
+    /// Apply a single `key=value` line. Returns true when a progress block is complete.
+    pub fn apply_line(&mut self, line: &str) -> bool {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            return false;
+        };
+
+        match key {
+            // Despite its name, out_time_ms is reported in microseconds
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(micros) = value.parse::<f64>() {
+                    self.out_time_sec = (micros / 1_000_000.0).max(0.0);
+                }
+            }
+            "speed" => self.speed = value.trim_end_matches('x').trim().parse().ok(),
+            "fps" => self.fps = value.parse().ok(),
+            "progress" => {
+                self.finished = value == "end";
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// One `-i` input and the options that apply to it
+#[derive(Debug, Clone)]
+pub struct Input {
+    options: Vec<String>,
+    path: String,
+}
+
+impl Input {
+    pub fn file(path: &str) -> Self {
+        Input {
+            options: Vec::new(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Input-side seek (`-ss`), which skips decoding everything before `sec`
+    pub fn seek(mut self, sec: f64) -> Self {
+        self.options.extend(["-ss".into(), sec.to_string()]);
+        self
+    }
+
+    /// Stop reading the input after `sec` seconds (`-t`)
+    pub fn duration(mut self, sec: f64) -> Self {
+        self.options.extend(["-t".into(), sec.to_string()]);
+        self
+    }
+
+    /// Force the demuxer (`-f`), e.g. `concat` or `lavfi`
+    pub fn format(mut self, format: &str) -> Self {
+        self.options.extend(["-f".into(), format.to_string()]);
+        self
+    }
+
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        self.options.extend([key.to_string(), value.to_string()]);
+        self
+    }
+}
+
+/// Typed builder for an ffmpeg invocation with a single output file
+#[derive(Debug, Clone)]
+pub struct FfmpegCommand {
+    inputs: Vec<Input>,
+    filter_complex: Option<String>,
+    maps: Vec<String>,
+    output_args: Vec<String>,
+    output: String,
+}
+
+impl FfmpegCommand {
+    pub fn new(output: &str) -> Self {
+        FfmpegCommand {
+            inputs: Vec::new(),
+            filter_complex: None,
+            maps: Vec::new(),
+            output_args: Vec::new(),
+            output: output.to_string(),
+        }
+    }
+
+    /// Add an input and return its index for stream specifiers like `0:v`
+    pub fn input(&mut self, input: Input) -> usize {
+        self.inputs.push(input);
+        self.inputs.len() - 1
+    }
+
+    pub fn filter_complex(&mut self, graph: &str) -> &mut Self {
+        self.filter_complex = Some(graph.to_string());
+        self
+    }
+
+    /// Map a stream specifier or filter label (`0:v:0`, `[outv]`) into the output
+    pub fn map(&mut self, spec: &str) -> &mut Self {
+        self.maps.push(spec.to_string());
+        self
+    }
+
+    /// A simple `-vf` chain; empty chains are skipped
+    pub fn video_filters(&mut self, filters: &[String]) -> &mut Self {
+        if !filters.is_empty() {
+            self.output_args.extend(["-vf".into(), filters.join(",")]);
+        }
+        self
+    }
+
+    /// A simple `-af` chain; empty chains are skipped
+    pub fn audio_filters(&mut self, filters: &[String]) -> &mut Self {
+        if !filters.is_empty() {
+            self.output_args.extend(["-af".into(), filters.join(",")]);
+        }
+        self
+    }
+
+    /// Output options such as codecs and muxer flags, placed before the output path
+    pub fn output_args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.output_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["-y".to_string()];
+        for input in &self.inputs {
+            args.extend(input.options.iter().cloned());
+            args.extend(["-i".to_string(), input.path.clone()]);
+        }
+        if let Some(graph) = &self.filter_complex {
+            args.extend(["-filter_complex".to_string(), graph.clone()]);
+        }
+        for spec in &self.maps {
+            args.extend(["-map".to_string(), spec.clone()]);
+        }
+        args.extend(self.output_args.iter().cloned());
+        args.push(self.output.clone());
+        args
+    }
+
+    /// Run the command. Returns ffmpeg's stderr, which some filters (e.g.
+    /// `loudnorm`) print their results to.
+    pub fn run<F>(&self, cancel: &CancelToken, on_progress: F) -> Result<String, String>
+    where
+        F: FnMut(&FfmpegProgress),
+    {
+        println!("🔧 FFmpeg command: ffmpeg {}", self.to_args().join(" "));
+        run_with_progress(&self.to_args(), cancel, on_progress)
+    }
+}
+
+/// Output path that discards everything, for analysis passes run with `-f null`
+pub fn null_output() -> &'static str {
+    if cfg!(windows) {
+        "NUL"
+    } else {
+        "/dev/null"
+    }
+}
+
+/// Run ffmpeg with `-progress pipe:1`, calling `on_progress` after every progress block.
+/// Returns ffmpeg's stderr; on failure the error contains it instead.
+fn run_with_progress<F>(
+    args: &[String],
+    cancel: &CancelToken,
+    mut on_progress: F,
+) -> Result<String, String>
+where
+    F: FnMut(&FfmpegProgress),
+{
+    if let Some(reason) = cancel.should_stop() {
+        return Err(reason.to_string());
+    }
+
+    let mut child = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-progress", "pipe:1"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
+
+    let stdout = child.stdout.take().ok_or("Failed to capture ffmpeg stdout")?;
+    let mut stderr = child.stderr.take().ok_or("Failed to capture ffmpeg stderr")?;
+
+    // Drain stderr on its own thread so ffmpeg never blocks on a full pipe
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = String::new();
+        let _ = stderr.read_to_string(&mut buf);
+        buf
+    });
+
+    // Hand the process to the token so cancel() can kill it while we read
+    let child_id = cancel.next_child.fetch_add(1, Ordering::SeqCst);
+    {
+        let mut children = cancel.children.lock().unwrap();
+        if cancel.should_stop().is_some() {
+            let _ = child.kill();
+        }
+        children.insert(child_id, child);
+    }
+
+    let mut progress = FfmpegProgress::default();
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else { break };
+        if progress.apply_line(&line) {
+            on_progress(&progress);
+        }
+    }
+
+    let mut child = cancel
+        .children
+        .lock()
+        .unwrap()
+        .remove(&child_id)
+        .ok_or("FFmpeg process handle was lost")?;
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    let stderr_output = stderr_reader.join().unwrap_or_default();
+
+    if let Some(reason) = cancel.should_stop() {
+        return Err(reason.to_string());
+    }
+    if !status.success() {
+        return Err(stderr_output);
+    }
+
+    Ok(stderr_output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_lines_are_parsed() {
+        let mut progress = FfmpegProgress::default();
+        assert!(!progress.apply_line("out_time_ms=2500000"));
+        assert!(!progress.apply_line("speed=1.5x"));
+        assert!(!progress.apply_line("fps=N/A"));
+        assert!(progress.apply_line("progress=continue"));
+        assert_eq!(progress.out_time_sec, 2.5);
+        assert_eq!(progress.speed, Some(1.5));
+        assert_eq!(progress.fps, None);
+        assert!(!progress.finished);
+        assert!(progress.apply_line("progress=end"));
+        assert!(progress.finished);
+    }
+
+    #[test]
+    fn command_builder_orders_arguments() {
+        let mut cmd = FfmpegCommand::new("out.mp4");
+        cmd.input(Input::file("a.mp4").seek(1.5).duration(2.0));
+        cmd.filter_complex("[0:v]null[v]").map("[v]");
+        cmd.output_args(["-c:v", "libx264"]);
+        assert_eq!(
+            cmd.to_args(),
+            vec![
+                "-y", "-ss", "1.5", "-t", "2", "-i", "a.mp4", "-filter_complex", "[0:v]null[v]",
+                "-map", "[v]", "-c:v", "libx264", "out.mp4"
+            ]
+        );
+    }
+}
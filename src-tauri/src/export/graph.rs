@@ -0,0 +1,315 @@
+use super::conform::{self, OutputFormat};
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::mix::{self, MixFormat};
+use super::settings::ExportSettings;
+use super::tracks::{self, VideoTrack};
+use super::transform;
+use super::{ClipData, ExportRequest};
+use crate::video::StreamInfo;
+
+/// A single-pass render of the timeline: one input per clip and one filter graph
+#[derive(Debug, Clone)]
+pub struct TimelineGraph {
+    pub inputs: Vec<Input>,
+    pub filter_complex: String,
+    pub video_label: String,
+    pub audio_label: Option<String>,
+    /// Format every clip was conformed to
+    pub format: OutputFormat,
+}
+
+impl TimelineGraph {
+    /// ffmpeg command that renders the graph to `output_path`
+    pub fn command(&self, settings: &ExportSettings, output_path: &str) -> FfmpegCommand {
+        let mut cmd = FfmpegCommand::new(output_path);
+        for input in &self.inputs {
+            cmd.input(input.clone());
+        }
+        cmd.filter_complex(&self.filter_complex)
+            .map(&format!("[{}]", self.video_label));
+        if let Some(audio) = &self.audio_label {
+            cmd.map(&format!("[{}]", audio));
+        }
+        cmd.output_args(settings.codec_args());
+        cmd
+    }
+
+    /// Composite overlay tracks over the joined video
+    pub fn append_overlays(&mut self, video_tracks: &[VideoTrack]) {
+        if !tracks::has_overlays(video_tracks) {
+            return;
+        }
+        let (inputs, chains) =
+            tracks::overlay_chains(video_tracks, &self.format, self.inputs.len(), &self.video_label, "outov");
+        self.inputs.extend(inputs);
+        for chain in chains {
+            self.filter_complex.push(';');
+            self.filter_complex.push_str(&chain);
+        }
+        self.video_label = "outov".to_string();
+    }
+
+    /// Mix the request's audio tracks with the clips' own audio
+    pub fn append_audio_mix(&mut self, request: &ExportRequest) {
+        if !mix::needs_mix(request) {
+            return;
+        }
+        let format = MixFormat {
+            sample_rate: self.format.sample_rate,
+            channel_layout: "stereo",
+        };
+        let (inputs, chains) =
+            mix::mix_chains(request, self.audio_label.as_deref(), format, self.inputs.len(), "outmix");
+        self.inputs.extend(inputs);
+        for chain in chains {
+            self.filter_complex.push(';');
+            self.filter_complex.push_str(&chain);
+        }
+        self.audio_label = Some("outmix".to_string());
+    }
+
+    /// Run the joined video through more filters, e.g. text overlays
+    pub fn append_video_filters(&mut self, filters: &[String]) {
+        if filters.is_empty() {
+            return;
+        }
+        self.filter_complex.push_str(&format!(
+            ";[{}]{}[outvf]",
+            self.video_label,
+            filters.join(",")
+        ));
+        self.video_label = "outvf".to_string();
+    }
+}
+
+/// Build a `trim`/`atrim`/`setpts`/`concat` graph for the clips, joining
+/// clips with `xfade`/`acrossfade` where they have a transition.
+/// `sources[i]` describes the file behind `clips[i]`. Returns an error for
+/// timelines the graph can't express, so the caller can fall back to trimming
+/// each clip separately.
+pub fn build(
+    clips: &[ClipData],
+    sources: &[StreamInfo],
+    settings: &ExportSettings,
+) -> Result<TimelineGraph, String> {
+    if clips.is_empty() || clips.len() != sources.len() {
+        return Err("Every clip needs probed stream info".to_string());
+    }
+
+    if let Some(i) = sources.iter().position(|s| !s.has_video) {
+        return Err(format!("Clip {} has no video stream", i));
+    }
+
+    // concat and xfade need every clip in the same format: one frame size,
+    // frame rate, time base and audio layout, with silence for clips without audio
+    let format = OutputFormat::choose(clips, sources, settings);
+    let has_audio = format.has_audio;
+
+    let mut inputs = Vec::new();
+    let mut chains = Vec::new();
+
+    for (i, clip) in clips.iter().enumerate() {
+        let duration = clip.source_duration();
+
+        // Seek on the input so ffmpeg doesn't decode everything before in_sec
+        inputs.push(
+            Input::file(&clip.source_path)
+                .seek(clip.in_sec)
+                .duration(duration),
+        );
+
+        let mut video = format!("[{}:v]trim=duration={},setpts=PTS-STARTPTS", i, duration);
+        for filter in clip.retime_video_filters() {
+            video.push(',');
+            video.push_str(&filter);
+        }
+        for filter in transform::clip_video_filters(clip, &sources[i], format.frame)
+            .into_iter()
+            .chain(format.video_filters())
+        {
+            video.push(',');
+            video.push_str(&filter);
+        }
+        chains.push(format!("{}[v{}]", video, i));
+
+        if has_audio {
+            let audio = if sources[i].has_audio {
+                clip_audio_chain(i, clip, &sources[i], format.audio_filters())
+            } else {
+                format.silence(clip.timeline_duration())
+            };
+            chains.push(format!("{}[a{}]", audio, i));
+        }
+    }
+
+    join_clips(clips, true, has_audio, &mut chains);
+    chains.push("[vcat]null[outv]".to_string());
+
+    Ok(TimelineGraph {
+        inputs,
+        filter_complex: chains.join(";"),
+        video_label: "outv".to_string(),
+        audio_label: has_audio.then(|| "outa".to_string()),
+        format,
+    })
+}
+
+/// Filters between trimming a clip's audio and conforming it: resync,
+/// cleanup, retiming and the volume envelope, in that order
+pub fn clip_audio_filters(clip: &ClipData, source: &StreamInfo) -> Vec<String> {
+    let mut filters = conform::audio_sync_filters(source);
+    filters.extend(clip.cleanup_filters());
+    filters.extend(clip.retime_audio_filters());
+    filters.extend(clip.envelope_filters());
+    filters
+}
+
+/// Audio chain of clip `index`, from its input to `format_filters`, without an output label
+pub fn clip_audio_chain(index: usize, clip: &ClipData, source: &StreamInfo, format_filters: Vec<String>) -> String {
+    let mut audio = format!("[{}:a]atrim=duration={},asetpts=PTS-STARTPTS", index, clip.source_duration());
+    for filter in clip_audio_filters(clip, source).into_iter().chain(format_filters) {
+        audio.push(',');
+        audio.push_str(&filter);
+    }
+    audio
+}
+
+/// Join the per-clip `[vN]`/`[aN]` streams into `[vcat]` and `[outa]`;
+/// `video`/`audio` say which of the two the clips have. Without transitions
+/// that's a single `concat`. Otherwise clips are joined one pair at a time:
+/// `xfade`/`acrossfade` where the earlier clip has a transition, a two-input
+/// `concat` for hard cuts.
+pub fn join_clips(clips: &[ClipData], video: bool, audio: bool, chains: &mut Vec<String>) {
+    if !clips.iter().any(|c| c.transition.is_some()) {
+        let inputs: String = (0..clips.len())
+            .map(|i| {
+                let v = if video { format!("[v{}]", i) } else { String::new() };
+                let a = if audio { format!("[a{}]", i) } else { String::new() };
+                v + &a
+            })
+            .collect();
+        let outputs = format!("{}{}", if video { "[vcat]" } else { "" }, if audio { "[outa]" } else { "" });
+        chains.push(format!(
+            "{}concat=n={}:v={}:a={}{}",
+            inputs,
+            clips.len(),
+            video as u8,
+            audio as u8,
+            outputs
+        ));
+        return;
+    }
+
+    let mut joined_video = "v0".to_string();
+    let mut joined_audio = "a0".to_string();
+    // Length of everything joined so far; a transition starts `duration` before its end
+    let mut joined_sec = clips[0].timeline_duration();
+
+    for (i, clip) in clips.iter().enumerate().skip(1) {
+        let last = i == clips.len() - 1;
+        let video_out = if last { "vcat".to_string() } else { format!("vj{}", i) };
+        let audio_out = if last { "outa".to_string() } else { format!("aj{}", i) };
+
+        match &clips[i - 1].transition {
+            Some(transition) => {
+                let duration = transition.duration_sec;
+                if video {
+                    chains.push(format!(
+                        "[{}][v{}]xfade=transition={}:duration={}:offset={}[{}]",
+                        joined_video,
+                        i,
+                        transition.xfade_name(),
+                        duration,
+                        joined_sec - duration,
+                        video_out
+                    ));
+                }
+                if audio {
+                    chains.push(format!(
+                        "[{}][a{}]acrossfade=d={}[{}]",
+                        joined_audio, i, duration, audio_out
+                    ));
+                }
+                joined_sec += clip.timeline_duration() - duration;
+            }
+            None => {
+                if video {
+                    chains.push(format!("[{}][v{}]concat=n=2:v=1:a=0[{}]", joined_video, i, video_out));
+                }
+                if audio {
+                    chains.push(format!("[{}][a{}]concat=n=2:v=0:a=1[{}]", joined_audio, i, audio_out));
+                }
+                joined_sec += clip.timeline_duration();
+            }
+        }
+
+        joined_video = video_out;
+        joined_audio = audio_out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{settings, transition};
+    use crate::export::test_support::*;
+
+    #[test]
+    fn graph_fits_mixed_resolutions_into_one_frame() {
+        let clips = vec![clip("a.mp4", 0.0, 1.0), clip("b.mp4", 0.0, 1.0)];
+        let sources = vec![
+            stream(320, 240, 25.0),
+            stream(640, 480, 25.0),
+        ];
+        // Without an output resolution the first clip sets the frame
+        let graph = build(&clips, &sources, &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("[0:v]trim=duration=1,setpts=PTS-STARTPTS,setsar=1,fps=25,format=yuv420p,settb=AVTB[v0]"));
+        assert!(graph.filter_complex.contains(
+            "scale=320:240:force_original_aspect_ratio=decrease:force_divisible_by=2,pad=320:240:(ow-iw)/2:(oh-ih)/2:color=black,setsar=1,fps=25"
+        ));
+
+        let scaled = ExportSettings {
+            resolution: Some(settings::Resolution { width: 640, height: 480 }),
+            ..ExportSettings::default()
+        };
+        let graph = build(&clips, &sources, &scaled).unwrap();
+        assert!(graph.filter_complex.contains("concat=n=2:v=1:a=1"));
+    }
+
+    #[test]
+    fn graph_offsets_xfades_by_joined_length() {
+        use transition::TransitionKind::{DipToBlack, Wipe};
+        let clips = vec![
+            with_transition(clip("a.mp4", 0.0, 2.0), DipToBlack, 0.5),
+            clip("b.mp4", 1.0, 4.0),
+            with_transition(clip("c.mp4", 0.0, 2.0), Wipe, 1.0),
+            clip("d.mp4", 0.0, 2.0),
+        ];
+        let source = stream(320, 240, 25.0);
+        let graph = build(&clips, &vec![source; 4], &ExportSettings::default()).unwrap();
+
+        assert!(graph.filter_complex.contains("[v0][v1]xfade=transition=fadeblack:duration=0.5:offset=1.5[vj1]"));
+        assert!(graph.filter_complex.contains("[a0][a1]acrossfade=d=0.5[aj1]"));
+        // Hard cut after b: 2 + 3 - 0.5 = 4.5s joined so far
+        assert!(graph.filter_complex.contains("[vj1][v2]concat=n=2:v=1:a=0[vj2]"));
+        assert!(graph.filter_complex.contains("[vj2][v3]xfade=transition=wipeleft:duration=1:offset=5.5[vcat]"));
+        assert!(graph.filter_complex.contains("[aj2][a3]acrossfade=d=1[outa]"));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn single_pass_export_matches_timeline_duration() {
+        let dir = test_dir("single_pass");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.5, 1.5), clip(&source, 2.0, 3.5)],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert!((probe_duration(&output) - 2.5).abs() < 0.15);
+    }
+}
@@ -0,0 +1,229 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::ffmpeg::CancelToken;
+use super::progress::RenderProgress;
+use super::ExportResult;
+
+/// How long a finished job's status stays available to `get_status`
+const FINISHED_JOB_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Lifecycle of a background export
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportJobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Progress payload emitted as the `export-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    pub job_id: String,
+    pub status: ExportJobState,
+    pub message: String,
+    #[serde(flatten)]
+    pub progress: RenderProgress,
+}
+
+/// Snapshot returned by `get_export_status`
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJobStatus {
+    pub job_id: String,
+    pub state: ExportJobState,
+    pub output_path: String,
+    pub progress: Option<ExportProgress>,
+    pub result: Option<ExportResult>,
+    pub error: Option<String>,
+}
+
+impl ExportJobStatus {
+    /// Final `export-progress` payload once the job has stopped running
+    pub fn final_progress(&self) -> ExportProgress {
+        let message = match self.state {
+            ExportJobState::Running => "Export running".to_string(),
+            ExportJobState::Completed => format!("Export completed: {}", self.output_path),
+            ExportJobState::Cancelled => "Export cancelled".to_string(),
+            ExportJobState::Failed => self.error.clone().unwrap_or_default(),
+        };
+        let progress = RenderProgress {
+            eta_sec: None,
+            speed: None,
+            fps: None,
+            ..self
+                .progress
+                .as_ref()
+                .map(|p| p.progress.clone())
+                .unwrap_or_default()
+        };
+
+        ExportProgress {
+            job_id: self.job_id.clone(),
+            status: self.state,
+            message,
+            progress,
+        }
+    }
+}
+
+/// A single background export. Intermediate files belong to the render's
+/// workspace, which is removed when the render returns.
+#[derive(Debug)]
+pub struct ExportJob {
+    pub id: String,
+    pub cancel: CancelToken,
+    status: Mutex<ExportJobStatus>,
+    finished_at: Mutex<Option<Instant>>,
+}
+
+impl ExportJob {
+    pub fn status(&self) -> ExportJobStatus {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Record render progress and return the event payload for it
+    pub fn set_progress(&self, progress: RenderProgress) -> ExportProgress {
+        let event = ExportProgress {
+            job_id: self.id.clone(),
+            status: ExportJobState::Running,
+            message: format!("{} ({:.0}%)", progress.stage, progress.percent),
+            progress,
+        };
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).progress = Some(event.clone());
+        event
+    }
+
+    /// Record the outcome of the export. Also called after the render
+    /// panicked, so poisoned locks are taken over rather than unwrapped.
+    pub fn finish(&self, result: Result<ExportResult, String>) -> ExportJobStatus {
+        *self.finished_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(result) => {
+                status.state = ExportJobState::Completed;
+                status.result = Some(result);
+            }
+            Err(_) if self.cancel.is_cancelled() => {
+                // Don't leave a truncated file behind at the destination
+                let _ = std::fs::remove_file(&status.output_path);
+                status.state = ExportJobState::Cancelled;
+            }
+            Err(e) => {
+                status.state = ExportJobState::Failed;
+                status.error = Some(e);
+            }
+        }
+        status.clone()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref EXPORT_JOBS: Mutex<HashMap<String, Arc<ExportJob>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Register a new running export
+pub fn create_job(output_path: &str) -> Arc<ExportJob> {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let id = format!("export_{}_{}", millis, NEXT_JOB_ID.fetch_add(1, Ordering::SeqCst));
+
+    let job = Arc::new(ExportJob {
+        id: id.clone(),
+        cancel: CancelToken::default(),
+        status: Mutex::new(ExportJobStatus {
+            job_id: id.clone(),
+            state: ExportJobState::Running,
+            output_path: output_path.to_string(),
+            progress: None,
+            result: None,
+            error: None,
+        }),
+        finished_at: Mutex::new(None),
+    });
+
+    let mut jobs = EXPORT_JOBS.lock().unwrap();
+    prune(&mut jobs, Instant::now());
+    jobs.insert(id, job.clone());
+    job
+}
+
+/// Forget jobs that finished more than `FINISHED_JOB_TTL` before `now`
+fn prune(jobs: &mut HashMap<String, Arc<ExportJob>>, now: Instant) {
+    jobs.retain(|_, job| match *job.finished_at.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(finished) => now.duration_since(finished) < FINISHED_JOB_TTL,
+        None => true,
+    });
+}
+
+/// Status of a running job, or of one that finished in the last ten minutes
+pub fn get_status(job_id: &str) -> Result<ExportJobStatus, String> {
+    let mut jobs = EXPORT_JOBS.lock().unwrap();
+    prune(&mut jobs, Instant::now());
+    jobs.get(job_id)
+        .map(|job| job.status())
+        .ok_or_else(|| format!("Unknown export job: {}", job_id))
+}
+
+/// Request cancellation; the render cleans up once ffmpeg exits
+pub fn cancel(job_id: &str) -> Result<(), String> {
+    let job = EXPORT_JOBS
+        .lock()
+        .unwrap()
+        .get(job_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown export job: {}", job_id))?;
+
+    if job.status().state != ExportJobState::Running {
+        return Err("Export is not running".to_string());
+    }
+
+    println!("🛑 Cancelling export job {}", job_id);
+    job.cancel.cancel();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finished_jobs_are_pruned_after_their_ttl() {
+        let running = create_job("running.mp4");
+        let done = create_job("done.mp4");
+        done.finish(Ok(ExportResult::default()));
+        assert_eq!(get_status(&done.id).unwrap().state, ExportJobState::Completed);
+
+        let mut jobs = EXPORT_JOBS.lock().unwrap();
+        prune(&mut jobs, Instant::now() + FINISHED_JOB_TTL / 2);
+        assert!(jobs.contains_key(&done.id));
+        prune(&mut jobs, Instant::now() + FINISHED_JOB_TTL);
+        assert!(!jobs.contains_key(&done.id));
+        // Running jobs are kept however old they are
+        assert!(jobs.contains_key(&running.id));
+        jobs.remove(&running.id);
+    }
+
+    #[test]
+    fn panicked_jobs_still_finish() {
+        let job = create_job("crashed.mp4");
+        let holder = job.clone();
+        let _ = std::thread::spawn(move || {
+            let _status = holder.status.lock().unwrap();
+            panic!("render crashed mid-update");
+        })
+        .join();
+
+        let status = job.finish(Err("The job stopped unexpectedly".to_string()));
+        assert_eq!(status.state, ExportJobState::Failed);
+        assert_eq!(get_status(&job.id).unwrap().state, ExportJobState::Failed);
+    }
+}
@@ -0,0 +1,239 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::ffmpeg::{null_output, FfmpegCommand, Input};
+use super::progress::ProgressTracker;
+use super::settings::LoudnessTarget;
+use super::ExportContext;
+use crate::video;
+
+/// Loudness of a mix as reported by `loudnorm`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoudnessStats {
+    pub integrated_lufs: f64,
+    pub true_peak_dbtp: f64,
+    pub loudness_range_lu: f64,
+    pub threshold_lufs: f64,
+}
+
+/// Loudness before and after normalization
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoudnessReport {
+    pub measured: LoudnessStats,
+    pub normalized: LoudnessStats,
+}
+
+/// Everything `loudnorm` prints in its JSON summary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnormSummary {
+    pub input: LoudnessStats,
+    pub output: LoudnessStats,
+    pub target_offset: f64,
+}
+
+/// Find the JSON block `loudnorm` prints at the end of ffmpeg's stderr
+pub fn parse_summary(log: &str) -> Result<LoudnormSummary, String> {
+    let start = log.rfind('{').ok_or("loudnorm didn't print a summary")?;
+    let end = log[start..].find('}').ok_or("loudnorm summary is incomplete")? + start;
+    let json: serde_json::Value = serde_json::from_str(&log[start..=end])
+        .map_err(|e| format!("Failed to parse loudnorm summary: {}", e))?;
+
+    // Values are strings, and "-inf" for silence
+    let field = |key: &str| -> Result<f64, String> {
+        json[key]
+            .as_str()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| format!("loudnorm summary is missing {}", key))
+    };
+    let stats = |prefix: &str| -> Result<LoudnessStats, String> {
+        Ok(LoudnessStats {
+            integrated_lufs: field(&format!("{}_i", prefix))?,
+            true_peak_dbtp: field(&format!("{}_tp", prefix))?,
+            loudness_range_lu: field(&format!("{}_lra", prefix))?,
+            threshold_lufs: field(&format!("{}_thresh", prefix))?,
+        })
+    };
+
+    Ok(LoudnormSummary {
+        input: stats("input")?,
+        output: stats("output")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+fn target_options(target: &LoudnessTarget) -> String {
+    format!(
+        "I={}:TP={}:LRA={}",
+        target.integrated_lufs, target.true_peak_dbtp, target.loudness_range_lu
+    )
+}
+
+/// First pass: only measures
+fn measure_filter(target: &LoudnessTarget) -> String {
+    format!("loudnorm={}:print_format=json", target_options(target))
+}
+
+/// Second pass: feeds the measurement back in so loudnorm can apply one
+/// linear gain instead of compressing dynamically
+pub fn correction_filter(target: &LoudnessTarget, measured: &LoudnormSummary) -> String {
+    format!(
+        "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true:print_format=json",
+        target_options(target),
+        measured.input.integrated_lufs,
+        measured.input.true_peak_dbtp,
+        measured.input.loudness_range_lu,
+        measured.input.threshold_lufs,
+        measured.target_offset
+    )
+}
+
+/// Measure the loudness of `input_path` as progress stage `stage`. None when
+/// there's no audible audio to normalize.
+pub fn measure(
+    input_path: &str,
+    target: &LoudnessTarget,
+    tracker: &Mutex<ProgressTracker>,
+    stage: usize,
+    ctx: &ExportContext,
+) -> Result<Option<LoudnormSummary>, String> {
+    let has_audio = video::probe_streams(input_path).map_err(|e| e.message)?.has_audio;
+    if !has_audio {
+        println!("⚠️ Nothing to normalize: the export has no audio");
+        return Ok(None);
+    }
+
+    let mut cmd = FfmpegCommand::new(null_output());
+    cmd.input(Input::file(input_path));
+    cmd.output_args(["-vn", "-af", &measure_filter(target), "-f", "null"]);
+    let log = ctx
+        .run_stage_with_log(&cmd, tracker, stage)
+        .map_err(|e| format!("FFmpeg loudness measurement failed: {}", e))?;
+    let measured = parse_summary(&log)?;
+    println!(
+        "🔊 Measured {:.1} LUFS, {:.1} dBTP, LRA {:.1} LU",
+        measured.input.integrated_lufs, measured.input.true_peak_dbtp, measured.input.loudness_range_lu
+    );
+
+    if !measured.input.integrated_lufs.is_finite() {
+        println!("⚠️ Nothing to normalize: the audio is silent");
+        return Ok(None);
+    }
+    Ok(Some(measured))
+}
+
+/// Report for an encode run with `correction_filter(measured)`, from its log
+pub fn report(measured: &LoudnormSummary, log: &str) -> Result<LoudnessReport, String> {
+    let normalized = parse_summary(log)?.output;
+    println!(
+        "✅ Normalized to {:.1} LUFS, {:.1} dBTP",
+        normalized.integrated_lufs, normalized.true_peak_dbtp
+    );
+    Ok(LoudnessReport {
+        measured: measured.input,
+        normalized,
+    })
+}
+
+/// Measure the audio of `input`, then write it to `output_path` at the target
+/// loudness. `output_args` are the encoder arguments for the output; they must
+/// set a sample rate, since loudnorm works at 192 kHz. Returns None, and just
+/// copies the file, when there's no audible audio to normalize.
+pub fn normalize(
+    input: &Path,
+    output_path: &str,
+    target: &LoudnessTarget,
+    output_args: Vec<String>,
+    duration: f64,
+    ctx: &ExportContext,
+) -> Result<Option<LoudnessReport>, String> {
+    let input_path = input.to_string_lossy().to_string();
+    let tracker = Mutex::new(ProgressTracker::new(vec![
+        ("Measuring loudness".to_string(), duration),
+        ("Normalizing loudness".to_string(), duration),
+    ]));
+
+    let Some(measured) = measure(&input_path, target, &tracker, 0, ctx)? else {
+        std::fs::copy(input, output_path).map_err(|e| format!("Failed to copy export: {}", e))?;
+        return Ok(None);
+    };
+
+    let mut cmd = FfmpegCommand::new(output_path);
+    cmd.input(Input::file(&input_path));
+    cmd.output_args(["-af".to_string(), correction_filter(target, &measured)])
+        .output_args(output_args);
+    let log = ctx
+        .run_stage_with_log(&cmd, &tracker, 1)
+        .map_err(|e| format!("FFmpeg loudness normalization failed: {}", e))?;
+    report(&measured, &log).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{settings, ExportRequest};
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn loudnorm_summary_is_parsed_and_fed_into_the_second_pass() {
+        let log = r#"[out#0/null @ 0x5589] video:0kB audio:9kB
+[Parsed_loudnorm_0 @ 0x5590]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-16.58",
+	"output_tp" : "-1.50",
+	"output_lra" : "14.78",
+	"output_thresh" : "-27.71",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.58"
+}"#;
+        let summary = parse_summary(log).unwrap();
+        assert_eq!(summary.input.integrated_lufs, -27.61);
+        assert_eq!(summary.input.loudness_range_lu, 18.06);
+        assert_eq!(summary.output.true_peak_dbtp, -1.5);
+
+        let target = settings::LoudnessTarget {
+            integrated_lufs: -16.0,
+            true_peak_dbtp: -1.5,
+            loudness_range_lu: 11.0,
+        };
+        assert_eq!(
+            correction_filter(&target, &summary),
+            "loudnorm=I=-16:TP=-1.5:LRA=11:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.2:offset=0.58:linear=true:print_format=json"
+        );
+
+        let silent = log.replace(r#""input_i" : "-27.61""#, r#""input_i" : "-inf""#);
+        assert!(parse_summary(&silent).unwrap().input.integrated_lufs.is_infinite());
+        assert!(parse_summary("no summary here").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn loudness_target_normalizes_the_final_mix() {
+        let dir = test_dir("loudness");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 4.0)],
+            output_path: output.clone(),
+            settings: ExportSettings {
+                loudness: Some(settings::LoudnessTarget {
+                    integrated_lufs: -23.0,
+                    true_peak_dbtp: -1.5,
+                    loudness_range_lu: 11.0,
+                }),
+                ..ExportSettings::default()
+            },
+            ..ExportRequest::default()
+        };
+        let report = export(&request).unwrap().loudness.unwrap();
+
+        assert!((report.normalized.integrated_lufs + 23.0).abs() < 1.0);
+        assert!((probe_duration(&output) - 4.0).abs() < 0.1);
+    }
+}
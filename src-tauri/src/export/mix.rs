@@ -0,0 +1,466 @@
+use serde::{Deserialize, Serialize};
+
+use super::conform;
+use super::ffmpeg::Input;
+use super::tracks::{AudioClip, AudioTrack, TrackMix};
+use super::ExportRequest;
+
+/// Peak ceiling of the final mix, -1 dBFS, so summed tracks can't clip
+const LIMITER_CEILING: f64 = 0.891;
+
+/// Lower a music track whenever someone is talking: the voice drives a
+/// compressor on the music
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ducking {
+    /// Index into `audio_tracks` of the track to duck
+    pub music_track: usize,
+    /// Index into `audio_tracks` of the voice; none uses the clips' own audio
+    #[serde(default)]
+    pub voice_track: Option<usize>,
+    /// Voice level above which the music is pulled down
+    #[serde(default = "default_threshold_db")]
+    pub threshold_db: f64,
+    #[serde(default = "default_ratio")]
+    pub ratio: f64,
+    #[serde(default = "default_attack_ms")]
+    pub attack_ms: f64,
+    #[serde(default = "default_release_ms")]
+    pub release_ms: f64,
+}
+
+fn default_threshold_db() -> f64 {
+    -30.0
+}
+
+fn default_ratio() -> f64 {
+    8.0
+}
+
+fn default_attack_ms() -> f64 {
+    20.0
+}
+
+fn default_release_ms() -> f64 {
+    400.0
+}
+
+impl Ducking {
+    pub fn validate(&self, tracks: &[AudioTrack]) -> Result<(), String> {
+        if self.music_track >= tracks.len() {
+            return Err(format!("Ducking needs a music track, but there's no audio track {}", self.music_track));
+        }
+        if let Some(voice) = self.voice_track {
+            if voice >= tracks.len() {
+                return Err(format!("Ducking voice track {} doesn't exist", voice));
+            }
+            if voice == self.music_track {
+                return Err("Ducking needs different voice and music tracks".to_string());
+            }
+        }
+        // Ranges sidechaincompress accepts
+        if !(-60.0..=0.0).contains(&self.threshold_db) {
+            return Err(format!("Ducking threshold must be between -60 and 0 dB, got {}", self.threshold_db));
+        }
+        if !(1.0..=20.0).contains(&self.ratio) {
+            return Err(format!("Ducking ratio must be between 1 and 20, got {}", self.ratio));
+        }
+        if !(0.01..=2000.0).contains(&self.attack_ms) {
+            return Err(format!("Ducking attack must be between 0.01 and 2000 ms, got {}", self.attack_ms));
+        }
+        if !(0.01..=9000.0).contains(&self.release_ms) {
+            return Err(format!("Ducking release must be between 0.01 and 9000 ms, got {}", self.release_ms));
+        }
+        Ok(())
+    }
+
+    /// `sidechaincompress` with the music on its first input and the voice on its second
+    pub fn compressor_filter(&self) -> String {
+        format!(
+            "sidechaincompress=threshold={}:ratio={}:attack={}:release={}",
+            10f64.powf(self.threshold_db / 20.0),
+            self.ratio,
+            self.attack_ms,
+            self.release_ms
+        )
+    }
+}
+
+/// Sample rate and channel layout the mix is rendered at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixFormat<'a> {
+    pub sample_rate: u32,
+    pub channel_layout: &'a str,
+}
+
+/// True when the request's audio needs mixing beyond the clips' own sound
+pub fn needs_mix(request: &ExportRequest) -> bool {
+    request.clip_audio != TrackMix::default() || request.audio_tracks.iter().any(|t| !t.clips.is_empty())
+}
+
+/// Whether any track, including the clips' own audio, is soloed
+fn any_solo(request: &ExportRequest) -> bool {
+    request.clip_audio.solo || request.audio_tracks.iter().any(|t| t.mix.solo)
+}
+
+/// `volume` filter for a gain, or nothing for unity gain
+fn gain_filter(gain_db: f64) -> Option<String> {
+    (gain_db != 0.0).then(|| format!("volume={}dB", gain_db))
+}
+
+/// Filters that fade a clip in and out
+fn fade_filters(clip: &AudioClip) -> Vec<String> {
+    let mut filters = Vec::new();
+    if clip.fade_in_sec > 0.0 {
+        filters.push(format!("afade=t=in:st=0:d={}", clip.fade_in_sec));
+    }
+    if clip.fade_out_sec > 0.0 {
+        filters.push(format!(
+            "afade=t=out:st={}:d={}",
+            clip.duration() - clip.fade_out_sec,
+            clip.fade_out_sec
+        ));
+    }
+    filters
+}
+
+/// Inputs and filter chains that mix the audio tracks with the timeline's own
+/// audio `[base]` (None when the clips are silent) into `[out]`. The mix is as
+/// long as the timeline and ends in a limiter. `first_input` is the ffmpeg
+/// input index of the first audio clip.
+pub fn mix_chains(
+    request: &ExportRequest,
+    base: Option<&str>,
+    format: MixFormat,
+    first_input: usize,
+    out: &str,
+) -> (Vec<Input>, Vec<String>) {
+    let any_solo = any_solo(request);
+    let mut inputs = Vec::new();
+    let mut chains = Vec::new();
+
+    // The clips' audio sets the length of the mix, so it stays in even when muted
+    let bed = format!("{}bed", out);
+    match base {
+        Some(base) if request.clip_audio.is_audible(any_solo) => {
+            let gain = gain_filter(request.clip_audio.gain_db).unwrap_or_else(|| "anull".to_string());
+            chains.push(format!("[{}]{}[{}]", base, gain, bed));
+        }
+        Some(base) => chains.push(format!("[{}]volume=0[{}]", base, bed)),
+        None => chains.push(format!(
+            "anullsrc=r={}:cl={},atrim=duration={}[{}]",
+            format.sample_rate,
+            format.channel_layout,
+            request.total_duration(),
+            bed
+        )),
+    }
+
+    // One stream per audible track, by index into `audio_tracks`
+    let mut streams: Vec<(usize, String)> = Vec::new();
+    for (t, track) in request.audio_tracks.iter().enumerate() {
+        if !track.mix.is_audible(any_solo) || track.clips.is_empty() {
+            continue;
+        }
+        let mut labels = Vec::new();
+        for clip in &track.clips {
+            let index = first_input + inputs.len();
+            inputs.push(Input::file(&clip.source_path).seek(clip.in_sec).duration(clip.duration()));
+
+            let mut filters = vec![format!("atrim=duration={}", clip.duration()), "asetpts=PTS-STARTPTS".to_string()];
+            filters.extend(conform::audio_format_filters(format.sample_rate, format.channel_layout));
+            filters.extend(clip.envelope_filters());
+            filters.extend(gain_filter(clip.gain_db + track.mix.gain_db));
+            filters.extend(fade_filters(clip));
+            filters.push(format!("adelay={}:all=1", (clip.start_sec * 1000.0).round() as u64));
+
+            let label = format!("{}{}", out, index);
+            chains.push(format!("[{}:a]{}[{}]", index, filters.join(","), label));
+            labels.push(label);
+        }
+        if labels.len() == 1 {
+            streams.push((t, labels.remove(0)));
+        } else {
+            let track_label = format!("{}track{}", out, t);
+            let track_inputs: String = labels.iter().map(|l| format!("[{}]", l)).collect();
+            chains.push(format!(
+                "{}amix=inputs={}:duration=longest:dropout_transition=0:normalize=0[{}]",
+                track_inputs,
+                labels.len(),
+                track_label
+            ));
+            streams.push((t, track_label));
+        }
+    }
+
+    let mut bed_label = bed;
+    if let Some(ducking) = &request.ducking {
+        duck(ducking, out, &mut bed_label, &mut streams, &mut chains);
+    }
+
+    let mut mix_inputs = format!("[{}]", bed_label);
+    for (_, label) in &streams {
+        mix_inputs.push_str(&format!("[{}]", label));
+    }
+    let count = streams.len() + 1;
+    // normalize=0 keeps every input at its own gain; the limiter catches the peaks instead
+    let mix = if count > 1 {
+        format!("amix=inputs={}:duration=first:dropout_transition=0:normalize=0,", count)
+    } else {
+        String::new()
+    };
+    chains.push(format!(
+        "{}{}alimiter=limit={}:level=0[{}]",
+        mix_inputs, mix, LIMITER_CEILING, out
+    ));
+    (inputs, chains)
+}
+
+/// Split the voice stream so one copy keys a compressor on the music stream,
+/// and swap the ducked music in. Does nothing when the music is silent or
+/// muted; a muted voice can't duck either.
+fn duck(
+    ducking: &Ducking,
+    out: &str,
+    bed: &mut String,
+    streams: &mut [(usize, String)],
+    chains: &mut Vec<String>,
+) {
+    let Some(music) = streams.iter().position(|(t, _)| *t == ducking.music_track) else {
+        println!("⚠️ Music track {} is silent, nothing to duck", ducking.music_track);
+        return;
+    };
+    let voice = match ducking.voice_track {
+        Some(track) => match streams.iter().position(|(t, _)| *t == track) {
+            Some(i) => &mut streams[i].1,
+            None => {
+                println!("⚠️ Voice track {} is silent, not ducking", track);
+                return;
+            }
+        },
+        None => bed,
+    };
+
+    let heard = format!("{}voice", out);
+    let key = format!("{}key", out);
+    // The key is padded so the compressor runs to the end of the music
+    chains.push(format!("[{}]asplit=2[{}][{}pre]", voice, heard, key));
+    chains.push(format!("[{}pre]apad[{}]", key, key));
+    *voice = heard;
+
+    let ducked = format!("{}ducked", out);
+    chains.push(format!(
+        "[{}][{}]{}[{}]",
+        streams[music].1,
+        key,
+        ducking.compressor_filter(),
+        ducked
+    ));
+    streams[music].1 = ducked;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{audio, graph, tracks, ExportTarget};
+    use crate::video;
+    use crate::export::audio::AudioSettings;
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn audio_tracks_mix_with_gain_fades_and_solo() {
+        let music = AudioTrack {
+            name: "Music".to_string(),
+            clips: vec![tracks::AudioClip {
+                source_path: "music.mp3".to_string(),
+                in_sec: 10.0,
+                out_sec: 16.0,
+                start_sec: 1.5,
+                gain_db: -12.0,
+                fade_in_sec: 1.0,
+                fade_out_sec: 2.0,
+                volume: None,
+            }],
+            mix: TrackMix { gain_db: 3.0, ..TrackMix::default() },
+        };
+        let mut request = ExportRequest {
+            clips: vec![clip("a.mp4", 0.0, 8.0)],
+            output_path: "out.mp4".to_string(),
+            audio_tracks: vec![music],
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+
+        let source = stream(640, 360, 30.0);
+        let mut graph = graph::build(&request.clips, std::slice::from_ref(&source), &ExportSettings::default()).unwrap();
+        graph.append_audio_mix(&request);
+        assert_eq!(graph.audio_label.as_deref(), Some("outmix"));
+        assert!(graph.filter_complex.contains("[outa]anull[outmixbed]"));
+        assert!(graph.filter_complex.contains(
+            "[1:a]atrim=duration=6,asetpts=PTS-STARTPTS,aresample=48000,aformat=sample_rates=48000:channel_layouts=stereo,\
+             volume=-9dB,afade=t=in:st=0:d=1,afade=t=out:st=4:d=2,adelay=1500:all=1[outmix1]"
+        ));
+        assert!(graph.filter_complex.contains(
+            "[outmixbed][outmix1]amix=inputs=2:duration=first:dropout_transition=0:normalize=0,alimiter=limit=0.891:level=0[outmix]"
+        ));
+
+        // Soloing the clips' audio silences the music entirely
+        request.clip_audio.solo = true;
+        let mut graph = graph::build(&request.clips, &[source], &ExportSettings::default()).unwrap();
+        graph.append_audio_mix(&request);
+        assert_eq!(graph.inputs.len(), 1);
+        assert!(graph.filter_complex.contains("[outmixbed]alimiter"));
+
+        request.audio_tracks[0].clips[0].fade_out_sec = 5.5;
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn ducking_keys_the_music_off_the_voice() {
+        let track = |name: &str, clips: Vec<(&str, f64, f64)>| AudioTrack {
+            name: name.to_string(),
+            clips: clips
+                .into_iter()
+                .map(|(path, start_sec, length)| tracks::AudioClip {
+                    source_path: path.to_string(),
+                    in_sec: 0.0,
+                    out_sec: length,
+                    start_sec,
+                    gain_db: 0.0,
+                    fade_in_sec: 0.0,
+                    fade_out_sec: 0.0,
+                    volume: None,
+                })
+                .collect(),
+            mix: TrackMix::default(),
+        };
+        let mut request = ExportRequest {
+            clips: vec![clip("talk.mp4", 0.0, 20.0)],
+            output_path: "out.m4a".to_string(),
+            audio_tracks: vec![
+                track("Music", vec![("music.mp3", 0.0, 20.0)]),
+                track("Voiceover", vec![("vo1.wav", 2.0, 3.0), ("vo2.wav", 10.0, 4.0)]),
+            ],
+            ducking: Some(Ducking {
+                music_track: 0,
+                voice_track: None,
+                threshold_db: -40.0,
+                ratio: 10.0,
+                attack_ms: 5.0,
+                release_ms: 300.0,
+            }),
+            target: ExportTarget::Audio(AudioSettings {
+                format: audio::AudioFormat::M4a,
+                bitrate_kbps: 192,
+                sample_rate: 48000,
+                channels: 2,
+                metadata: audio::AudioMetadata::default(),
+            }),
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+
+        // The clips' own audio ducks the music in audio-only exports too
+        let ExportTarget::Audio(settings) = request.target.clone() else { unreachable!() };
+        let args = audio::command(&request, &[stream(640, 360, 30.0)], &settings).to_args().join(" ");
+        assert!(args.contains("[outmixbed]asplit=2[outmixvoice][outmixkeypre];[outmixkeypre]apad[outmixkey]"));
+        assert!(args.contains(
+            "[outmix1][outmixkey]sidechaincompress=threshold=0.01:ratio=10:attack=5:release=300[outmixducked]"
+        ));
+        // The voiceover clips are mixed into one track stream first
+        assert!(args.contains("[outmix2][outmix3]amix=inputs=2:duration=longest:dropout_transition=0:normalize=0[outmixtrack1]"));
+        assert!(args.contains("[outmixvoice][outmixducked][outmixtrack1]amix=inputs=3"));
+
+        // ...or a voiceover track does
+        request.ducking.as_mut().unwrap().voice_track = Some(1);
+        let args = audio::command(&request, &[stream(640, 360, 30.0)], &settings).to_args().join(" ");
+        assert!(args.contains("[outmixtrack1]asplit=2[outmixvoice][outmixkeypre]"));
+        assert!(args.contains("[outmixbed][outmixducked][outmixvoice]amix=inputs=3"));
+
+        request.ducking.as_mut().unwrap().voice_track = Some(0);
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn music_track_is_mixed_into_silent_clips() {
+        let dir = test_dir("music_bed");
+        let silent = make_source(&dir, "screen.mp4", 3.0, "320x240", false);
+        let music = make_source(&dir, "music.mp4", 5.0, "160x120", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&silent, 0.0, 3.0)],
+            output_path: output.clone(),
+            audio_tracks: vec![AudioTrack {
+                name: "Music".to_string(),
+                clips: vec![tracks::AudioClip {
+                    source_path: music,
+                    in_sec: 0.0,
+                    out_sec: 5.0,
+                    start_sec: 0.5,
+                    gain_db: -6.0,
+                    fade_in_sec: 0.5,
+                    fade_out_sec: 0.5,
+                    volume: None,
+                }],
+                mix: TrackMix::default(),
+            }],
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        // The music outlasts the clips but is cut to the timeline
+        assert!(video::probe_streams(&output).unwrap().has_audio);
+        assert!((probe_duration(&output) - 3.0).abs() < 0.15);
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn ducked_music_renders_in_full_and_audio_exports() {
+        let dir = test_dir("ducking");
+        let talk = make_source(&dir, "talk.mp4", 3.0, "320x240", true);
+        let music = make_source(&dir, "music.mp4", 3.0, "160x120", true);
+
+        let mut request = ExportRequest {
+            clips: vec![clip(&talk, 0.0, 3.0)],
+            output_path: dir.join("out.mp4").to_string_lossy().to_string(),
+            audio_tracks: vec![AudioTrack {
+                name: "Music".to_string(),
+                clips: vec![tracks::AudioClip {
+                    source_path: music,
+                    in_sec: 0.0,
+                    out_sec: 3.0,
+                    start_sec: 0.0,
+                    gain_db: 0.0,
+                    fade_in_sec: 0.0,
+                    fade_out_sec: 0.0,
+                    volume: None,
+                }],
+                mix: TrackMix::default(),
+            }],
+            ducking: Some(Ducking {
+                music_track: 0,
+                voice_track: None,
+                threshold_db: -30.0,
+                ratio: 8.0,
+                attack_ms: 20.0,
+                release_ms: 400.0,
+            }),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+        assert!((probe_duration(&request.output_path) - 3.0).abs() < 0.15);
+
+        request.output_path = dir.join("out.wav").to_string_lossy().to_string();
+        request.target = ExportTarget::Audio(AudioSettings {
+            format: audio::AudioFormat::Wav,
+            bitrate_kbps: 192,
+            sample_rate: 48000,
+            channels: 2,
+            metadata: audio::AudioMetadata::default(),
+        });
+        export(&request).unwrap();
+        assert!((probe_duration(&request.output_path) - 3.0).abs() < 0.15);
+    }
+}
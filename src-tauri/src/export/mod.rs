@@ -0,0 +1,692 @@
+//! Export engine: turns a timeline of clips into a rendered file.
+//!
+//! Tauri commands build an [`ExportRequest`] and call [`run`]; everything
+//! below that (ffmpeg arguments, temp files, progress) lives in this module.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::video;
+
+pub mod animated;
+pub mod audio;
+pub mod cache;
+pub mod cleanup;
+mod concat;
+mod conform;
+pub mod envelope;
+pub mod ffmpeg;
+mod graph;
+pub mod jobs;
+pub mod loudness;
+pub mod mix;
+mod pool;
+pub mod progress;
+pub mod retime;
+pub mod settings;
+mod smart;
+pub mod subtitles;
+mod target_size;
+pub mod text;
+pub mod tracks;
+pub mod transform;
+pub mod transition;
+mod workspace;
+
+#[cfg(test)]
+mod test_support;
+
+use animated::{AnimatedResult, AnimatedSettings};
+use audio::AudioSettings;
+use cache::SegmentCache;
+use cleanup::AudioCleanup;
+use envelope::VolumeEnvelope;
+use ffmpeg::{CancelToken, FfmpegCommand, FfmpegProgress};
+use loudness::LoudnessReport;
+use mix::Ducking;
+use progress::{ProgressTracker, RenderProgress};
+use retime::FreezeFrame;
+use settings::{ExportMode, ExportSettings, QualityMode, VideoCodec};
+use subtitles::{BurnInSubtitles, SubtitleTrack};
+use text::TextOverlay;
+use tracks::{AudioTrack, TrackMix, VideoTrack};
+use transform::ClipTransform;
+use transition::Transition;
+use workspace::Workspace;
+
+pub use smart::RenderedSegment;
+
+/// A clip on the timeline: the `in_sec..out_sec` range of a source file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipData {
+    pub source_path: String,
+    pub in_sec: f64,
+    pub out_sec: f64,
+    /// Transition into the next clip; none means a hard cut
+    #[serde(default)]
+    pub transition: Option<Transition>,
+    /// Playback speed, 0.25x to 16x; audio keeps its pitch
+    #[serde(default = "retime::default_speed")]
+    pub speed: f64,
+    /// Play the clip backwards
+    #[serde(default)]
+    pub reverse: bool,
+    /// Hold the frame at `in_sec` instead of playing the clip; `out_sec` is ignored
+    #[serde(default)]
+    pub freeze: Option<FreezeFrame>,
+    /// Crop, rotate, flip and how the clip is fitted into the output frame
+    #[serde(default)]
+    pub transform: Option<ClipTransform>,
+    /// Gain keyframed over the clip's audio
+    #[serde(default)]
+    pub volume: Option<VolumeEnvelope>,
+    /// Denoise, hum removal and other fixes for the clip's audio
+    #[serde(default)]
+    pub cleanup: Option<AudioCleanup>,
+}
+
+impl ClipData {
+    /// Seconds read from the source file
+    pub fn source_duration(&self) -> f64 {
+        match self.freeze {
+            Some(_) => retime::FREEZE_READ_SEC,
+            None => self.out_sec - self.in_sec,
+        }
+    }
+
+    /// Seconds the clip lasts on the output timeline, after speed changes and freezes
+    pub fn timeline_duration(&self) -> f64 {
+        match self.freeze {
+            Some(freeze) => freeze.hold_sec,
+            None => (self.out_sec - self.in_sec) / self.speed,
+        }
+    }
+}
+
+/// What kind of file the export produces
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportTarget {
+    /// A regular video file, encoded with the export settings
+    #[default]
+    Video,
+    /// An animated GIF or WebP
+    Animated(AnimatedSettings),
+    /// Only the timeline's audio, e.g. for podcasts
+    Audio(AudioSettings),
+}
+
+/// Everything needed to render one export
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub clips: Vec<ClipData>,
+    pub output_path: String,
+    #[serde(default)]
+    pub settings: ExportSettings,
+    #[serde(default)]
+    pub mode: ExportMode,
+    /// Render clip by clip through the segment cache so unchanged clips are reused
+    #[serde(default)]
+    pub use_cache: bool,
+    #[serde(default)]
+    pub text_overlays: Vec<TextOverlay>,
+    /// Transcript captions burned into the video
+    #[serde(default)]
+    pub burn_in_subtitles: Option<BurnInSubtitles>,
+    /// Selectable subtitle tracks muxed into the output
+    #[serde(default)]
+    pub subtitle_tracks: Vec<SubtitleTrack>,
+    #[serde(default)]
+    pub target: ExportTarget,
+    /// Video tracks composited over `clips`, stacked by z-order
+    #[serde(default)]
+    pub video_tracks: Vec<VideoTrack>,
+    /// Gain, mute and solo of the clips' own audio in the mix
+    #[serde(default)]
+    pub clip_audio: TrackMix,
+    /// Music, voiceover and effects mixed with the clips' audio
+    #[serde(default)]
+    pub audio_tracks: Vec<AudioTrack>,
+    /// Pull a music track down under speech
+    #[serde(default)]
+    pub ducking: Option<Ducking>,
+}
+
+impl ExportRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.clips.is_empty() {
+            return Err("No clips to export".to_string());
+        }
+        for (i, clip) in self.clips.iter().enumerate() {
+            if clip.in_sec < 0.0 || (clip.freeze.is_none() && clip.out_sec <= clip.in_sec) {
+                return Err(format!(
+                    "Clip {} has an invalid range: {}s to {}s",
+                    i, clip.in_sec, clip.out_sec
+                ));
+            }
+            clip.validate_retime(i)?;
+            if let Some(transform) = &clip.transform {
+                transform.validate(i)?;
+            }
+            if let Some(volume) = &clip.volume {
+                volume.validate(&format!("Clip {}", i))?;
+            }
+            if let Some(cleanup) = &clip.cleanup {
+                cleanup.validate(&format!("Clip {}", i))?;
+            }
+        }
+        self.validate_transitions()?;
+        let timeline_sec = self.total_duration();
+        for track in &self.video_tracks {
+            track.validate(timeline_sec)?;
+        }
+        self.clip_audio.validate("Clip audio")?;
+        for (i, track) in self.audio_tracks.iter().enumerate() {
+            track.validate(i)?;
+        }
+        if let Some(ducking) = &self.ducking {
+            ducking.validate(&self.audio_tracks)?;
+        }
+        for overlay in &self.text_overlays {
+            overlay.validate()?;
+        }
+        if let Some(subtitles) = &self.burn_in_subtitles {
+            subtitles.style.validate()?;
+        }
+        for track in &self.subtitle_tracks {
+            track.validate()?;
+        }
+        if self.subtitle_tracks.iter().filter(|t| t.default).count() > 1 {
+            return Err("Only one subtitle track can be the default".to_string());
+        }
+        if self.mode == ExportMode::SmartRender {
+            let blockers = self.smart_render_blockers();
+            if !blockers.is_empty() {
+                return Err(format!(
+                    "Smart render copies video as-is and can't do {}; use a full re-encode",
+                    blockers.join(", ")
+                ));
+            }
+        }
+        if let ExportTarget::Animated(animated) = &self.target {
+            animated.validate()?;
+            if !self.subtitle_tracks.is_empty() {
+                return Err("Animated exports can't carry subtitle tracks; burn the captions in instead".to_string());
+            }
+        }
+        if let ExportTarget::Audio(audio) = &self.target {
+            audio.validate()?;
+            if !self.text_overlays.is_empty() || self.burn_in_subtitles.is_some() || !self.subtitle_tracks.is_empty() {
+                return Err("Audio exports have no video for text or subtitles".to_string());
+            }
+        }
+        self.settings.validate()
+    }
+
+    fn validate_transitions(&self) -> Result<(), String> {
+        let last = self.clips.len() - 1;
+        for (i, clip) in self.clips.iter().enumerate() {
+            let Some(transition) = &clip.transition else { continue };
+            if i == last {
+                return Err(format!("Clip {} is the last clip and can't have a transition", i));
+            }
+            let duration = transition.duration_sec;
+            if duration <= 0.0 {
+                return Err(format!("Transition after clip {} needs a positive duration", i));
+            }
+            let next = &self.clips[i + 1];
+            if duration > clip.timeline_duration() || duration > next.timeline_duration() {
+                return Err(format!(
+                    "Transition after clip {} is {}s but the clips around it are {:.2}s and {:.2}s",
+                    i,
+                    duration,
+                    clip.timeline_duration(),
+                    next.timeline_duration()
+                ));
+            }
+            // The next clip also has to fit its own outgoing transition
+            let next_out = next.transition.map(|t| t.duration_sec).unwrap_or(0.0);
+            if duration + next_out > next.timeline_duration() {
+                return Err(format!(
+                    "Clip {} is too short for the transitions on both of its sides",
+                    i + 1
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where each clip starts on the output timeline
+    pub fn clip_start_times(&self) -> Vec<f64> {
+        clip_start_times(&self.clips)
+    }
+
+    pub fn has_transitions(&self) -> bool {
+        self.clips.iter().any(|c| c.transition.is_some())
+    }
+
+    /// Features in the request that need every frame decoded, so smart
+    /// render can't stream-copy around them
+    pub fn smart_render_blockers(&self) -> Vec<&'static str> {
+        let clips = &self.clips;
+        let checks = [
+            (self.has_transitions(), "transitions"),
+            (!self.text_overlays.is_empty() || self.burn_in_subtitles.is_some(), "text or burned-in subtitles"),
+            (clips.iter().any(ClipData::is_retimed), "speed changes, reverse or freeze frames"),
+            (clips.iter().any(|c| c.transform.is_some()), "crop, rotate or flip"),
+            (clips.iter().any(|c| c.volume.is_some()), "volume envelopes"),
+            (clips.iter().any(|c| c.cleanup.is_some()), "audio cleanup"),
+            (tracks::has_overlays(&self.video_tracks), "overlay tracks"),
+            (mix::needs_mix(self), "audio track mixing"),
+            (matches!(self.settings.quality, QualityMode::TargetSize { .. }), "a target file size"),
+            (matches!(self.target, ExportTarget::Animated(_)), "animated output"),
+            (self.settings.resolution.is_some(), "a set resolution"),
+            (self.settings.fps.is_some(), "a set frame rate"),
+            // Only H.264 and HEVC segments are copied, and WebM carries neither
+            (
+                !matches!(self.settings.video_codec, VideoCodec::H264 | VideoCodec::H265),
+                "VP9 or AV1 video",
+            ),
+        ];
+        checks.iter().filter(|(blocked, _)| *blocked).map(|(_, feature)| *feature).collect()
+    }
+
+    /// Length of the rendered timeline: transitions overlap neighbouring clips
+    pub fn total_duration(&self) -> f64 {
+        let overlap: f64 = self
+            .clips
+            .iter()
+            .filter_map(|c| c.transition.map(|t| t.duration_sec))
+            .sum();
+        self.clips.iter().map(ClipData::timeline_duration).sum::<f64>() - overlap
+    }
+}
+
+/// Where each clip starts on the output timeline: after the previous clip,
+/// minus the overlap of any transition between them
+pub fn clip_start_times(clips: &[ClipData]) -> Vec<f64> {
+    let mut starts = Vec::with_capacity(clips.len());
+    let mut position = 0.0;
+    for clip in clips {
+        starts.push(position);
+        position += clip.timeline_duration() - clip.transition.map(|t| t.duration_sec).unwrap_or(0.0);
+    }
+    starts
+}
+
+/// What a finished export produced
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportResult {
+    /// Smart render only: which parts were stream-copied and which re-encoded
+    pub segments: Vec<RenderedSegment>,
+    /// Clips taken from the segment cache instead of being rendered
+    pub reused_segments: usize,
+    /// Animated exports only: the fps and width the file was encoded at
+    pub animated: Option<AnimatedResult>,
+    /// Target size exports only: the video bitrate picked to hit the size
+    pub video_bitrate_kbps: Option<u32>,
+    /// Loudness of the mix before and after normalization, when a target was set
+    pub loudness: Option<LoudnessReport>,
+}
+
+/// Hooks the caller provides: a cancel token and a progress callback. The
+/// callback may be called from several worker threads.
+pub struct ExportContext<'a> {
+    pub cancel: &'a CancelToken,
+    pub on_progress: &'a (dyn Fn(RenderProgress) + Sync),
+}
+
+impl ExportContext<'_> {
+    fn report(&self, tracker: &ProgressTracker, stage: usize, ffmpeg: Option<&FfmpegProgress>) {
+        (self.on_progress)(tracker.snapshot(stage, ffmpeg));
+    }
+
+    /// Run one ffmpeg command as a progress stage. The tracker is shared so
+    /// stages running on different workers add up to one overall percentage.
+    fn run_stage(
+        &self,
+        cmd: &FfmpegCommand,
+        tracker: &Mutex<ProgressTracker>,
+        stage: usize,
+    ) -> Result<(), String> {
+        self.run_stage_with_log(cmd, tracker, stage).map(|_| ())
+    }
+
+    /// `run_stage` that also returns ffmpeg's stderr
+    fn run_stage_with_log(
+        &self,
+        cmd: &FfmpegCommand,
+        tracker: &Mutex<ProgressTracker>,
+        stage: usize,
+    ) -> Result<String, String> {
+        let log = cmd.run(self.cancel, |p| {
+            let mut tracker = tracker.lock().unwrap();
+            tracker.update(stage, p.out_time_sec);
+            self.report(&tracker, stage, Some(p));
+        })?;
+        let mut tracker = tracker.lock().unwrap();
+        tracker.complete(stage);
+        self.report(&tracker, stage, None);
+        Ok(log)
+    }
+}
+
+/// Render `request` to its output path
+pub fn run(request: &ExportRequest, ctx: &ExportContext) -> Result<ExportResult, String> {
+    request.validate()?;
+    println!(
+        "🎬 Exporting {} clips to {}",
+        request.clips.len(),
+        request.output_path
+    );
+
+    let workspace = Workspace::create()?;
+
+    if let ExportTarget::Animated(settings) = &request.target {
+        let animated = animated::render(request, settings, &workspace, ctx)?;
+        return Ok(ExportResult {
+            animated: Some(animated),
+            ..ExportResult::default()
+        });
+    }
+
+    if let ExportTarget::Audio(settings) = &request.target {
+        return render_audio(request, settings, &workspace, ctx);
+    }
+
+    // Target size exports normalize inside their second pass
+    let normalize = request
+        .settings
+        .loudness
+        .filter(|_| !matches!(request.settings.quality, QualityMode::TargetSize { .. }));
+    if request.subtitle_tracks.is_empty() && normalize.is_none() {
+        return render(request, &workspace, ctx);
+    }
+
+    // Render to a temp file first, then normalize and/or add subtitle tracks on the way to the real output
+    let extension = request.settings.container.extension();
+    let mut rendered = workspace.path(&format!("render.{}", extension));
+    let render_request = ExportRequest {
+        output_path: rendered.to_string_lossy().to_string(),
+        ..request.clone()
+    };
+    let mut result = render(&render_request, &workspace, ctx)?;
+
+    if let Some(target) = &normalize {
+        let normalized = if request.subtitle_tracks.is_empty() {
+            PathBuf::from(&request.output_path)
+        } else {
+            workspace.path(&format!("normalized.{}", extension))
+        };
+        // Only the audio changes, so the video is copied as-is
+        let mut args: Vec<String> = ["-map", "0:v?", "-map", "0:a", "-c:v", "copy"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        args.extend(request.settings.audio_codec_args());
+        args.extend(request.settings.muxer_args());
+        result.loudness = loudness::normalize(
+            &rendered,
+            &normalized.to_string_lossy(),
+            target,
+            args,
+            request.total_duration(),
+            ctx,
+        )?;
+        rendered = normalized;
+    }
+
+    if !request.subtitle_tracks.is_empty() {
+        mux_subtitle_tracks(request, &rendered, &workspace, ctx)?;
+    }
+    Ok(result)
+}
+
+/// Audio-only export, rendered losslessly first when it's going to be normalized
+fn render_audio(
+    request: &ExportRequest,
+    settings: &AudioSettings,
+    workspace: &Workspace,
+    ctx: &ExportContext,
+) -> Result<ExportResult, String> {
+    let Some(target) = &request.settings.loudness else {
+        audio::render(request, settings, ctx)?;
+        return Ok(ExportResult::default());
+    };
+
+    let mix = workspace.path("mix.wav");
+    let mix_request = ExportRequest {
+        output_path: mix.to_string_lossy().to_string(),
+        ..request.clone()
+    };
+    let mix_settings = AudioSettings {
+        format: audio::AudioFormat::Wav,
+        metadata: audio::AudioMetadata::default(),
+        ..settings.clone()
+    };
+    audio::render(&mix_request, &mix_settings, ctx)?;
+
+    let loudness = loudness::normalize(
+        &mix,
+        &request.output_path,
+        target,
+        settings.codec_args(),
+        request.total_duration(),
+        ctx,
+    )?;
+    Ok(ExportResult {
+        loudness,
+        ..ExportResult::default()
+    })
+}
+
+/// Render the timeline with whichever pipeline fits the request
+fn render(request: &ExportRequest, workspace: &Workspace, ctx: &ExportContext) -> Result<ExportResult, String> {
+    if let QualityMode::TargetSize { size_bytes } = request.settings.quality {
+        return target_size::render(request, size_bytes, workspace, ctx);
+    }
+    if request.mode == ExportMode::SmartRender {
+        let segments = smart::render(request, workspace, ctx)?;
+        return Ok(ExportResult {
+            segments,
+            ..ExportResult::default()
+        });
+    }
+
+    let sources = request
+        .clips
+        .iter()
+        .map(|clip| video::probe_streams(&clip.source_path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.message)?;
+    // Crops can only be checked once the sources are probed
+    for (i, (clip, source)) in request.clips.iter().zip(&sources).enumerate() {
+        if let Some(transform) = &clip.transform {
+            transform.validate_crop_within(i, source.width, source.height)?;
+        }
+    }
+    let post_filters = video_post_filters(request, &sources, workspace)?;
+
+    // Overlays and audio tracks span clip boundaries just like transitions, so
+    // they all need the single-pass graph
+    let has_tracks = tracks::has_overlays(&request.video_tracks) || mix::needs_mix(request);
+    if request.use_cache && request.has_transitions() {
+        println!("⚠️ Transitions span clip boundaries, rendering without the segment cache");
+    } else if request.use_cache && has_tracks {
+        println!("⚠️ Overlay and audio tracks span clip boundaries, rendering without the segment cache");
+    } else if request.use_cache {
+        let cache = SegmentCache::open()?;
+        let reused_segments = {
+            let _in_use = cache.in_use();
+            concat::render(request, &sources, workspace, Some(&cache), &post_filters, ctx)?
+        };
+        cache.evict(cache::MAX_CACHE_BYTES);
+        return Ok(ExportResult {
+            reused_segments,
+            ..ExportResult::default()
+        });
+    }
+
+    match graph::build(&request.clips, &sources, &request.settings) {
+        Ok(mut graph) => {
+            graph.append_overlays(&request.video_tracks);
+            graph.append_audio_mix(request);
+            graph.append_video_filters(&post_filters);
+            println!("🧩 Filter graph: {}", graph.filter_complex);
+            let cmd = graph.command(&request.settings, &request.output_path);
+            let tracker = Mutex::new(ProgressTracker::new(vec![(
+                "Rendering timeline".to_string(),
+                request.total_duration(),
+            )]));
+            ctx.run_stage(&cmd, &tracker, 0)
+                .map_err(|e| format!("FFmpeg render failed: {}", e))?;
+        }
+        Err(reason) if request.has_transitions() => {
+            return Err(format!("Can't render transitions: {}", reason));
+        }
+        Err(reason) if has_tracks => {
+            return Err(format!("Can't render overlay or audio tracks: {}", reason));
+        }
+        Err(reason) => {
+            println!("⚠️ Single-pass render not possible ({}), trimming clips separately", reason);
+            concat::render(request, &sources, workspace, None, &post_filters, ctx)?;
+        }
+    }
+
+    Ok(ExportResult::default())
+}
+
+/// Copy the rendered file to the output path, adding every subtitle track
+fn mux_subtitle_tracks(
+    request: &ExportRequest,
+    rendered: &std::path::Path,
+    workspace: &Workspace,
+    ctx: &ExportContext,
+) -> Result<(), String> {
+    let container = request.settings.container;
+    // Clips were transformed and fitted on the way in, so ask the rendered file
+    let rendered_info = video::probe_streams(&rendered.to_string_lossy()).map_err(|e| e.message)?;
+    let frame_size = (rendered_info.width, rendered_info.height);
+    let clip_starts = request.clip_start_times();
+
+    let tracks = request
+        .subtitle_tracks
+        .iter()
+        .enumerate()
+        .map(|(i, track)| {
+            let file = track.write(
+                &request.clips,
+                &clip_starts,
+                container,
+                frame_size,
+                &workspace.path(&format!("subtitles_{}", i)),
+            )?;
+            Ok((track, file))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    println!("💬 Muxing {} subtitle tracks", tracks.len());
+
+    let cmd = subtitles::mux_command(rendered, &tracks, container, &request.output_path);
+    let tracker = Mutex::new(ProgressTracker::new(vec![(
+        "Adding subtitle tracks".to_string(),
+        request.total_duration(),
+    )]));
+    ctx.run_stage(&cmd, &tracker, 0)
+        .map_err(|e| format!("FFmpeg subtitle mux failed: {}", e))
+}
+
+/// Filters drawn over the joined timeline: burned-in captions first, then
+/// text overlays on top of them
+fn video_post_filters(
+    request: &ExportRequest,
+    sources: &[video::StreamInfo],
+    workspace: &Workspace,
+) -> Result<Vec<String>, String> {
+    let mut filters = Vec::new();
+
+    if let Some(burn_in) = &request.burn_in_subtitles {
+        let cues = subtitles::remap_segments(
+            &request.clips,
+            &request.clip_start_times(),
+            &burn_in.transcripts,
+        );
+        let cues = subtitles::wrap_cues(&cues, burn_in.style.max_chars_per_line);
+        println!("💬 Burning in {} captions", cues.len());
+
+        let frame = transform::output_frame(&request.clips, sources, &request.settings);
+        let (width, height) = (frame.width, frame.height);
+        let ass_path = workspace.path("captions.ass");
+        std::fs::write(&ass_path, subtitles::to_ass(&cues, &burn_in.style, width, height)?)
+            .map_err(|e| format!("Failed to write subtitles: {}", e))?;
+        filters.push(subtitles::burn_in_filter(&ass_path, &burn_in.style));
+    }
+
+    filters.extend(text::filters(&request.text_overlays));
+    Ok(filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::*;
+
+    #[test]
+    fn smart_render_lists_every_feature_it_cant_copy() {
+        let mut request = ExportRequest {
+            clips: vec![clip("a.mp4", 0.0, 2.0), clip("b.mp4", 0.0, 2.0)],
+            output_path: "out.mp4".to_string(),
+            mode: ExportMode::SmartRender,
+            ..ExportRequest::default()
+        };
+        assert!(request.smart_render_blockers().is_empty());
+        assert!(request.validate().is_ok());
+
+        request.clips[0] = with_transition(request.clips[0].clone(), transition::TransitionKind::Crossfade, 0.5);
+        request.clips[1].speed = 2.0;
+        request.settings.quality = QualityMode::TargetSize { size_bytes: 10_000_000 };
+        assert_eq!(
+            request.smart_render_blockers(),
+            vec!["transitions", "speed changes, reverse or freeze frames", "a target file size"]
+        );
+        let error = request.validate().unwrap_err();
+        assert!(error.contains("can't do transitions, speed changes, reverse or freeze frames, a target file size"));
+
+        // Output settings the copied video can't follow
+        request.clips[0].transition = None;
+        request.clips[1].speed = 1.0;
+        request.settings = ExportSettings {
+            resolution: Some(settings::Resolution { width: 1280, height: 720 }),
+            fps: Some(30.0),
+            video_codec: VideoCodec::Vp9,
+            container: settings::Container::Webm,
+            ..ExportSettings::default()
+        };
+        assert_eq!(
+            request.smart_render_blockers(),
+            vec!["a set resolution", "a set frame rate", "VP9 or AV1 video"]
+        );
+
+        // A full re-encode handles all of them
+        request.mode = ExportMode::Reencode;
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn cancelled_export_stops_without_output() {
+        let dir = test_dir("cancel");
+        let source = make_source(&dir, "src.mp4", 2.0, "320x240", false);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 2.0)],
+            output_path: output,
+            ..ExportRequest::default()
+        };
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let result = run(&request, &ExportContext { cancel: &cancel, on_progress: &|_| {} });
+        assert_eq!(result.unwrap_err(), "FFmpeg render failed: Export cancelled");
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::ffmpeg::CancelToken;
+
+/// Number of workers for `count` independent jobs: one per CPU, never more
+/// than there are jobs
+pub fn worker_count(count: usize) -> usize {
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    cpus.min(count).max(1)
+}
+
+/// Encoder threads each worker's ffmpeg may use so the pool doesn't oversubscribe the CPU
+pub fn threads_per_worker(workers: usize) -> usize {
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    (cpus / workers.max(1)).max(1)
+}
+
+/// Run `task` for every item on at most `workers` threads and return the
+/// results in item order. After the first failure no new items are started
+/// and `cancel` is aborted so ffmpeg processes still running are killed;
+/// that first error is returned.
+pub fn run_ordered<T, R, F>(
+    items: &[T],
+    workers: usize,
+    cancel: &CancelToken,
+    task: F,
+) -> Result<Vec<R>, String>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> Result<R, String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let first_error: Mutex<Option<String>> = Mutex::new(None);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                if failed.load(Ordering::SeqCst) || cancel.is_cancelled() {
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(i) else { break };
+
+                match task(i, item) {
+                    Ok(result) => results.lock().unwrap()[i] = Some(result),
+                    Err(e) => {
+                        // Workers killed by the abort below fail too; only the first error counts
+                        if !failed.swap(true, Ordering::SeqCst) {
+                            *first_error.lock().unwrap() = Some(e);
+                            cancel.abort();
+                        }
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(e) = first_error.into_inner().unwrap() {
+        return Err(e);
+    }
+    // Every slot is filled unless a cancel stopped the workers early
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .collect::<Option<Vec<R>>>()
+        .ok_or_else(|| "Export cancelled".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_pool_keeps_order_and_stops_after_failure() {
+        let items: Vec<usize> = (0..50).collect();
+        let cancel = CancelToken::default();
+        let doubled = run_ordered(&items, 4, &cancel, |_, n| {
+            // Finish out of order
+            std::thread::sleep(std::time::Duration::from_millis((50 - *n as u64) % 7));
+            Ok(n * 2)
+        })
+        .unwrap();
+        assert_eq!(doubled, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+
+        let started = std::sync::atomic::AtomicUsize::new(0);
+        let cancel = CancelToken::default();
+        let result: Result<Vec<()>, String> = run_ordered(&items, 2, &cancel, |i, _| {
+            started.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if i == 3 {
+                return Err("clip 3 failed".to_string());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            Ok(())
+        });
+        assert_eq!(result.unwrap_err(), "clip 3 failed");
+        assert!(started.load(std::sync::atomic::Ordering::SeqCst) < items.len());
+        // A failure aborts the token but doesn't count as a user cancel
+        assert!(!cancel.is_cancelled());
+    }
+}
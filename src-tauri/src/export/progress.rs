@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::time::Instant;
+
+use super::ffmpeg::FfmpegProgress;
+
+/// Overall progress of a render, independent of who is listening
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RenderProgress {
+    pub stage: String,
+    pub percent: f64,
+    pub eta_sec: Option<f64>,
+    pub speed: Option<f64>,
+    pub fps: Option<f64>,
+}
+
+/// Tracks overall progress across export stages, each weighted by the
+/// seconds of media it processes
+pub struct ProgressTracker {
+    stages: Vec<(String, f64)>,
+    done: Vec<f64>,
+    started: Instant,
+}
+
+impl ProgressTracker {
+    pub fn new(stages: Vec<(String, f64)>) -> Self {
+        let done = vec![0.0; stages.len()];
+        ProgressTracker {
+            stages,
+            done,
+            started: Instant::now(),
+        }
+    }
+
+    /// Record how many seconds of media the given stage has written so far
+    pub fn update(&mut self, stage: usize, out_time_sec: f64) {
+        if let Some((_, weight)) = self.stages.get(stage) {
+            self.done[stage] = out_time_sec.clamp(0.0, *weight);
+        }
+    }
+
+    pub fn complete(&mut self, stage: usize) {
+        if let Some((_, weight)) = self.stages.get(stage) {
+            self.done[stage] = *weight;
+        }
+    }
+
+    pub fn stage_label(&self, stage: usize) -> &str {
+        self.stages
+            .get(stage)
+            .map(|(label, _)| label.as_str())
+            .unwrap_or("")
+    }
+
+    pub fn snapshot(&self, stage: usize, ffmpeg: Option<&FfmpegProgress>) -> RenderProgress {
+        RenderProgress {
+            stage: self.stage_label(stage).to_string(),
+            percent: self.percent(),
+            eta_sec: self.eta_sec(),
+            speed: ffmpeg.and_then(|p| p.speed),
+            fps: ffmpeg.and_then(|p| p.fps),
+        }
+    }
+
+    /// Overall completion in percent (0-100)
+    pub fn percent(&self) -> f64 {
+        let total: f64 = self.stages.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (self.done.iter().sum::<f64>() / total * 100.0).clamp(0.0, 100.0)
+    }
+
+    /// Estimated seconds remaining, extrapolated from elapsed wall-clock time
+    pub fn eta_sec(&self) -> Option<f64> {
+        let percent = self.percent();
+        if percent < 1.0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(elapsed * (100.0 - percent) / percent)
+    }
+}
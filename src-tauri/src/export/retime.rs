@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+
+use super::ClipData;
+
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 16.0;
+/// Longest stretch of source that can play in reverse. ffmpeg's `reverse`
+/// holds every decoded frame in memory, about 3 MB each at 1080p, so a
+/// minute at 30fps is already over 5 GB.
+pub const MAX_REVERSE_SEC: f64 = 60.0;
+/// How much of the source a freeze frame reads to be sure it gets a frame
+pub const FREEZE_READ_SEC: f64 = 1.0;
+
+/// Hold a single source frame instead of playing the clip
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FreezeFrame {
+    pub hold_sec: f64,
+}
+
+pub fn default_speed() -> f64 {
+    1.0
+}
+
+/// `atempo` only takes 0.5x to 2x per instance, so bigger changes are chained
+pub fn atempo_chain(speed: f64) -> Vec<String> {
+    let mut filters = Vec::new();
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        filters.push("atempo=2".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        filters.push("atempo=0.5".to_string());
+        remaining *= 2.0;
+    }
+    if (remaining - 1.0).abs() > 1e-9 {
+        filters.push(format!("atempo={}", remaining));
+    }
+    filters
+}
+
+impl ClipData {
+    pub fn validate_retime(&self, index: usize) -> Result<(), String> {
+        if let Some(freeze) = &self.freeze {
+            if freeze.hold_sec <= 0.0 {
+                return Err(format!("Freeze frame on clip {} needs a positive length", index));
+            }
+            if self.reverse || self.speed != 1.0 {
+                return Err(format!(
+                    "Clip {} is a freeze frame and can't also change speed or play in reverse",
+                    index
+                ));
+            }
+        }
+        if self.reverse && self.out_sec - self.in_sec > MAX_REVERSE_SEC {
+            return Err(format!(
+                "Clip {} is {:.1}s long; reverse works on clips up to {}s, so split it first",
+                index,
+                self.out_sec - self.in_sec,
+                MAX_REVERSE_SEC
+            ));
+        }
+        if !(MIN_SPEED..=MAX_SPEED).contains(&self.speed) {
+            return Err(format!(
+                "Clip {} speed must be between {}x and {}x, got {}x",
+                index, MIN_SPEED, MAX_SPEED, self.speed
+            ));
+        }
+        Ok(())
+    }
+
+    /// True when the clip plays at anything other than normal speed and direction
+    pub fn is_retimed(&self) -> bool {
+        self.speed != 1.0 || self.reverse || self.freeze.is_some()
+    }
+
+    /// Video filters that retime the clip, applied after it's trimmed and its
+    /// timestamps start at zero
+    pub fn retime_video_filters(&self) -> Vec<String> {
+        if let Some(freeze) = &self.freeze {
+            return vec![
+                "trim=end_frame=1".to_string(),
+                format!("tpad=stop_mode=clone:stop_duration={}", freeze.hold_sec),
+            ];
+        }
+        let mut filters = Vec::new();
+        // reverse buffers the whole clip in memory
+        if self.reverse {
+            filters.push("reverse".to_string());
+        }
+        if self.speed != 1.0 {
+            filters.push(format!("setpts=PTS/{}", self.speed));
+        }
+        filters
+    }
+
+    /// Audio counterpart of `retime_video_filters`. Freeze frames are silent.
+    pub fn retime_audio_filters(&self) -> Vec<String> {
+        if let Some(freeze) = &self.freeze {
+            return vec![
+                "atrim=end_sample=1".to_string(),
+                "volume=0".to_string(),
+                format!("apad=whole_dur={}", freeze.hold_sec),
+            ];
+        }
+        let mut filters = Vec::new();
+        if self.reverse {
+            filters.push("areverse".to_string());
+        }
+        filters.extend(atempo_chain(self.speed));
+        filters
+    }
+
+    /// Where a moment of the source lands within the clip on the output
+    /// timeline, or None if the clip doesn't show it
+    pub fn source_to_clip_time(&self, source_sec: f64) -> Option<f64> {
+        if self.freeze.is_some() || source_sec < self.in_sec || source_sec > self.out_sec {
+            return None;
+        }
+        Some(self.source_offset_to_clip_time(source_sec))
+    }
+
+    /// `source_to_clip_time` without the range check: moments outside the
+    /// clip map to times before its start or after its end
+    pub fn source_offset_to_clip_time(&self, source_sec: f64) -> f64 {
+        let offset = if self.reverse {
+            self.out_sec - source_sec
+        } else {
+            source_sec - self.in_sec
+        };
+        offset / self.speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{graph, subtitles, ExportRequest};
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn retimed_clips_change_durations_filters_and_captions() {
+        assert_eq!(atempo_chain(0.25), vec!["atempo=0.5", "atempo=0.5"]);
+        assert_eq!(atempo_chain(3.0), vec!["atempo=2", "atempo=1.5"]);
+        assert_eq!(atempo_chain(16.0).len(), 4);
+        assert!(atempo_chain(1.0).is_empty());
+
+        let fast = ClipData { speed: 2.0, reverse: true, ..clip("a.mp4", 4.0, 10.0) };
+        let frozen = ClipData { freeze: Some(FreezeFrame { hold_sec: 1.5 }), ..clip("a.mp4", 2.0, 2.0) };
+        let request = ExportRequest {
+            clips: vec![fast.clone(), frozen.clone()],
+            output_path: "out.mp4".to_string(),
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+        assert!((request.total_duration() - 4.5).abs() < 1e-9);
+        assert_eq!(request.clip_start_times(), vec![0.0, 3.0]);
+        assert!(ClipData { speed: 20.0, ..fast.clone() }.validate_retime(0).is_err());
+        assert!(ClipData { speed: 2.0, ..frozen.clone() }.validate_retime(1).is_err());
+        assert!(ClipData { out_sec: 4.0 + MAX_REVERSE_SEC, ..fast.clone() }.validate_retime(0).is_ok());
+        assert!(ClipData { out_sec: 5.0 + MAX_REVERSE_SEC, ..fast.clone() }.validate_retime(0).is_err());
+
+        let source = stream(640, 360, 30.0);
+        let graph = graph::build(&request.clips, &[source.clone(), source], &ExportSettings::default()).unwrap();
+        assert!(graph.filter_complex.contains("[0:v]trim=duration=6,setpts=PTS-STARTPTS,reverse,setpts=PTS/2"));
+        assert!(graph.filter_complex.contains("areverse,atempo=2,aresample=48000"));
+        assert!(graph.filter_complex.contains("trim=end_frame=1,tpad=stop_mode=clone:stop_duration=1.5,setsar=1,fps=30"));
+        assert!(graph.filter_complex.contains("apad=whole_dur=1.5,aresample=48000"));
+
+        // Reversed at 2x: source 5..6 plays 2..2.5s into the clip
+        let mut transcripts = std::collections::HashMap::new();
+        transcripts.insert(
+            "a.mp4".to_string(),
+            vec![crate::transcription::TranscriptSegment { start_sec: 5.0, end_sec: 6.0, text: "back".to_string() }],
+        );
+        let cues = subtitles::remap_segments(&request.clips, &request.clip_start_times(), &transcripts);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_sec, cues[0].end_sec), (2.0, 2.5));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn speed_changes_and_freeze_frames_render_at_timeline_length() {
+        let dir = test_dir("retime");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![
+                ClipData { speed: 2.0, ..clip(&source, 0.0, 4.0) },
+                ClipData { freeze: Some(FreezeFrame { hold_sec: 1.5 }), ..clip(&source, 1.0, 1.0) },
+                ClipData { reverse: true, speed: 0.5, ..clip(&source, 3.0, 4.0) },
+            ],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert!((probe_duration(&output) - 5.5).abs() < 0.15);
+    }
+}
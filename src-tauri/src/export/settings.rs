@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+
+/// How the timeline is turned into the output file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportMode {
+    /// Re-encode everything with the export settings
+    #[default]
+    Reencode,
+    /// Stream-copy whole GOPs and re-encode only around the cuts
+    SmartRender,
+}
+
+/// Video encoder used for the export (software encoders only)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoCodec {
+    H264,
+    H265,
+    Vp9,
+    Av1,
+}
+
+impl VideoCodec {
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libaom-av1",
+        }
+    }
+
+    fn max_crf(&self) -> u32 {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 => 51,
+            VideoCodec::Vp9 | VideoCodec::Av1 => 63,
+        }
+    }
+}
+
+/// Constant quality (CRF), a target average bitrate, or a target file size
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum QualityMode {
+    Crf { crf: u32 },
+    Bitrate { kbps: u32 },
+    /// Two-pass encode at whatever bitrate makes the file this size
+    TargetSize { size_bytes: u64 },
+}
+
+/// Output container format
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Container {
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::Mkv => "mkv",
+            Container::Webm => "webm",
+        }
+    }
+
+    pub fn audio_encoder(&self) -> &'static str {
+        match self {
+            Container::Webm => "libopus",
+            _ => "aac",
+        }
+    }
+
+    pub fn supports(&self, codec: VideoCodec) -> bool {
+        match self {
+            Container::Mp4 => codec != VideoCodec::Vp9,
+            Container::Mov => matches!(codec, VideoCodec::H264 | VideoCodec::H265),
+            Container::Mkv => true,
+            Container::Webm => matches!(codec, VideoCodec::Vp9 | VideoCodec::Av1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// EBU R128 loudness the final mix is normalized to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessTarget {
+    /// Integrated loudness, e.g. -16 LUFS for web or -23 LUFS for broadcast
+    pub integrated_lufs: f64,
+    #[serde(default = "default_true_peak")]
+    pub true_peak_dbtp: f64,
+    #[serde(default = "default_loudness_range")]
+    pub loudness_range_lu: f64,
+}
+
+fn default_true_peak() -> f64 {
+    -1.5
+}
+
+fn default_loudness_range() -> f64 {
+    11.0
+}
+
+impl LoudnessTarget {
+    /// Ranges `loudnorm` accepts
+    fn validate(&self) -> Result<(), String> {
+        if !(-70.0..=-5.0).contains(&self.integrated_lufs) {
+            return Err(format!(
+                "Loudness target must be between -70 and -5 LUFS, got {}",
+                self.integrated_lufs
+            ));
+        }
+        if !(-9.0..=0.0).contains(&self.true_peak_dbtp) {
+            return Err(format!(
+                "True peak limit must be between -9 and 0 dBTP, got {}",
+                self.true_peak_dbtp
+            ));
+        }
+        if !(1.0..=50.0).contains(&self.loudness_range_lu) {
+            return Err(format!(
+                "Loudness range must be between 1 and 50 LU, got {}",
+                self.loudness_range_lu
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Encoding settings for an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// Output size; clips are fitted into it by their scale mode (letterboxed by default).
+    /// None uses the first clip's size.
+    pub resolution: Option<Resolution>,
+    /// Output frame rate. None keeps the source rate.
+    pub fps: Option<f64>,
+    pub video_codec: VideoCodec,
+    pub quality: QualityMode,
+    pub audio_bitrate_kbps: u32,
+    pub audio_sample_rate: u32,
+    pub container: Container,
+    /// Normalize the final mix to this loudness in two passes. None leaves levels alone.
+    pub loudness: Option<LoudnessTarget>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            resolution: None,
+            fps: None,
+            video_codec: VideoCodec::H264,
+            quality: QualityMode::Crf { crf: 22 },
+            audio_bitrate_kbps: 128,
+            audio_sample_rate: 48000,
+            container: Container::Mp4,
+            loudness: None,
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.container.supports(self.video_codec) {
+            return Err(format!(
+                "{:?} video cannot be written to a .{} file",
+                self.video_codec,
+                self.container.extension()
+            ));
+        }
+
+        if let Some(res) = self.resolution {
+            if res.width == 0 || res.height == 0 || res.width % 2 != 0 || res.height % 2 != 0 {
+                return Err(format!(
+                    "Invalid resolution {}x{}: width and height must be even and non-zero",
+                    res.width, res.height
+                ));
+            }
+        }
+
+        if let Some(fps) = self.fps {
+            if !(1.0..=120.0).contains(&fps) {
+                return Err(format!("Frame rate must be between 1 and 120 fps, got {}", fps));
+            }
+        }
+
+        match self.quality {
+            QualityMode::Crf { crf } if crf > self.video_codec.max_crf() => {
+                return Err(format!(
+                    "CRF {} is out of range for {:?} (0-{})",
+                    crf,
+                    self.video_codec,
+                    self.video_codec.max_crf()
+                ));
+            }
+            QualityMode::Bitrate { kbps: 0 } => {
+                return Err("Video bitrate must be greater than zero".to_string());
+            }
+            QualityMode::TargetSize { size_bytes: 0 } => {
+                return Err("Target file size must be greater than zero".to_string());
+            }
+            QualityMode::TargetSize { .. }
+                if !matches!(self.video_codec, VideoCodec::H264 | VideoCodec::Vp9) =>
+            {
+                return Err(format!(
+                    "Target size exports use two-pass H.264 or VP9, not {:?}",
+                    self.video_codec
+                ));
+            }
+            _ => {}
+        }
+
+        if self.audio_bitrate_kbps == 0 || self.audio_sample_rate == 0 {
+            return Err("Audio bitrate and sample rate must be greater than zero".to_string());
+        }
+
+        if let Some(loudness) = &self.loudness {
+            loudness.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Encoder and muxer arguments, placed just before the output path
+    pub fn codec_args(&self) -> Vec<String> {
+        let mut args = self.video_codec_args();
+        args.extend(self.audio_codec_args());
+        args.extend(self.muxer_args());
+        args
+    }
+
+    /// Audio encoder arguments only, e.g. when the video is stream-copied
+    pub fn audio_codec_args(&self) -> Vec<String> {
+        vec![
+            "-c:a".into(),
+            self.container.audio_encoder().into(),
+            "-b:a".into(),
+            format!("{}k", self.audio_bitrate_kbps),
+            "-ar".into(),
+            self.audio_sample_rate.to_string(),
+        ]
+    }
+
+    pub fn muxer_args(&self) -> Vec<String> {
+        if matches!(self.container, Container::Mp4 | Container::Mov) {
+            vec!["-movflags".into(), "+faststart".into()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Video encoder arguments only, e.g. for the first pass of a two-pass encode
+    pub fn video_codec_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-c:v".into(), self.video_codec.encoder().into()];
+
+        match self.quality {
+            QualityMode::Crf { crf } => {
+                args.extend(["-crf".into(), crf.to_string()]);
+                // libvpx/libaom only use constant quality when the bitrate cap is zero
+                if matches!(self.video_codec, VideoCodec::Vp9 | VideoCodec::Av1) {
+                    args.extend(["-b:v".into(), "0".into()]);
+                }
+            }
+            QualityMode::Bitrate { kbps } => {
+                args.extend(["-b:v".into(), format!("{}k", kbps)]);
+            }
+            // Resolved to a bitrate before encoding, see `target_size`
+            QualityMode::TargetSize { .. } => {}
+        }
+
+        match self.video_codec {
+            VideoCodec::H264 | VideoCodec::H265 => {
+                args.extend(["-preset".into(), "fast".into()]);
+            }
+            VideoCodec::Vp9 => {
+                args.extend(["-deadline".into(), "good".into(), "-cpu-used".into(), "4".into()]);
+                args.extend(["-row-mt".into(), "1".into()]);
+            }
+            VideoCodec::Av1 => {
+                args.extend(["-cpu-used".into(), "6".into(), "-row-mt".into(), "1".into()]);
+            }
+        }
+        args.extend(["-pix_fmt".into(), "yuv420p".into()]);
+
+        // QuickTime only plays HEVC tagged as hvc1
+        if self.video_codec == VideoCodec::H265
+            && matches!(self.container, Container::Mp4 | Container::Mov)
+        {
+            args.extend(["-tag:v".into(), "hvc1".into()]);
+        }
+
+        args
+    }
+}
+
+/// Named export settings offered in the export dialog
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportPreset {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub settings: ExportSettings,
+}
+
+pub fn presets() -> Vec<ExportPreset> {
+    vec![
+        ExportPreset {
+            id: "1080p_web".to_string(),
+            name: "1080p web".to_string(),
+            description: "1920x1080 H.264/AAC MP4 for sharing and upload".to_string(),
+            settings: ExportSettings {
+                resolution: Some(Resolution { width: 1920, height: 1080 }),
+                fps: Some(30.0),
+                quality: QualityMode::Crf { crf: 22 },
+                audio_bitrate_kbps: 160,
+                ..ExportSettings::default()
+            },
+        },
+        ExportPreset {
+            id: "720p_small".to_string(),
+            name: "720p small".to_string(),
+            description: "1280x720 H.264/AAC MP4 tuned for small files".to_string(),
+            settings: ExportSettings {
+                resolution: Some(Resolution { width: 1280, height: 720 }),
+                fps: Some(30.0),
+                quality: QualityMode::Crf { crf: 28 },
+                audio_bitrate_kbps: 96,
+                ..ExportSettings::default()
+            },
+        },
+        ExportPreset {
+            id: "25mb_attachment".to_string(),
+            name: "25 MB attachment".to_string(),
+            description: "1280x720 H.264/AAC MP4 sized to fit a 25 MB upload limit".to_string(),
+            settings: ExportSettings {
+                resolution: Some(Resolution { width: 1280, height: 720 }),
+                fps: Some(30.0),
+                quality: QualityMode::TargetSize { size_bytes: 25_000_000 },
+                audio_bitrate_kbps: 96,
+                ..ExportSettings::default()
+            },
+        },
+        ExportPreset {
+            id: "archive".to_string(),
+            name: "Archive".to_string(),
+            description: "Source resolution H.265 MKV at near-lossless quality".to_string(),
+            settings: ExportSettings {
+                video_codec: VideoCodec::H265,
+                quality: QualityMode::Crf { crf: 16 },
+                audio_bitrate_kbps: 256,
+                container: Container::Mkv,
+                ..ExportSettings::default()
+            },
+        },
+    ]
+}
@@ -0,0 +1,481 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+
+use super::audio::{self, AudioFormat, AudioMetadata, AudioSettings};
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::pool;
+use super::progress::ProgressTracker;
+use super::settings::{Container, VideoCodec};
+use super::workspace::Workspace;
+use super::{ExportContext, ExportRequest};
+use crate::video;
+
+/// Cuts closer than this to a keyframe count as landing on it
+const KEYFRAME_EPSILON: f64 = 0.001;
+
+/// Part of a clip that is either stream-copied or re-encoded
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedSegment {
+    pub clip_index: usize,
+    pub start_sec: f64,
+    pub end_sec: f64,
+    pub reencoded: bool,
+}
+
+/// Video stream parameters that must match for segments to be joined with
+/// `-c copy`, and that re-encoded segments are encoded with
+#[derive(Debug, Clone, PartialEq)]
+struct VideoStreamParams {
+    codec: String,
+    profile: String,
+    /// As ffprobe reports it: 31 for H.264 level 3.1, 93 for HEVC level 3.1
+    level: i64,
+    width: u64,
+    height: u64,
+    pix_fmt: String,
+    color_range: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    timescale: String,
+}
+
+impl VideoStreamParams {
+    /// Encoder arguments for a re-encoded segment whose bitstream has to
+    /// decode the same way as the copied GOPs around it. Parameter sets are
+    /// repeated in-band so each segment carries its own.
+    fn encoder_args(&self) -> Result<Vec<String>, String> {
+        let profile = self.profile.to_lowercase();
+        let mut args: Vec<String> = match self.codec.as_str() {
+            "h264" => {
+                let profile = match profile.as_str() {
+                    "baseline" | "constrained baseline" => "baseline",
+                    "main" => "main",
+                    "high" => "high",
+                    "high 10" => "high10",
+                    "high 4:2:2" => "high422",
+                    "high 4:4:4 predictive" => "high444",
+                    other => return Err(format!("Smart render can't match H.264 profile {}", other)),
+                };
+                vec![
+                    "-c:v".into(), "libx264".into(),
+                    "-profile:v".into(), profile.into(),
+                    "-level:v".into(), format!("{:.1}", self.level as f64 / 10.0),
+                    "-x264-params".into(), "repeat-headers=1".into(),
+                ]
+            }
+            "hevc" => {
+                let profile = match profile.as_str() {
+                    "main" => "main",
+                    "main 10" => "main10",
+                    other => return Err(format!("Smart render can't match HEVC profile {}", other)),
+                };
+                vec![
+                    "-c:v".into(), "libx265".into(),
+                    "-profile:v".into(), profile.into(),
+                    "-x265-params".into(),
+                    format!("repeat-headers=1:level-idc={:.1}", self.level as f64 / 30.0),
+                ]
+            }
+            other => return Err(format!("Smart render does not support {} sources", other)),
+        };
+        args.extend(["-preset".into(), "fast".into(), "-crf".into(), "18".into()]);
+        args.extend(["-pix_fmt".into(), self.pix_fmt.clone()]);
+        for (flag, value) in [
+            ("-color_range", &self.color_range),
+            ("-colorspace", &self.color_space),
+            ("-color_trc", &self.color_transfer),
+            ("-color_primaries", &self.color_primaries),
+        ] {
+            if let Some(value) = value {
+                args.extend([flag.to_string(), value.clone()]);
+            }
+        }
+        Ok(args)
+    }
+
+    /// Export codec setting for the source codec, for the ones smart render copies
+    fn video_codec(&self) -> Option<VideoCodec> {
+        match self.codec.as_str() {
+            "h264" => Some(VideoCodec::H264),
+            "hevc" => Some(VideoCodec::H265),
+            _ => None,
+        }
+    }
+
+    /// Bitstream filter that turns copied packets into Annex B, with the
+    /// parameter sets from the container written in front of each keyframe
+    fn annexb_filter(&self) -> String {
+        format!("{}_mp4toannexb", self.codec)
+    }
+}
+
+fn probe_video_params(path: &str) -> Result<VideoStreamParams, String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries",
+            "stream=codec_name,profile,level,width,height,pix_fmt,color_range,color_space,color_transfer,color_primaries,time_base",
+            "-of", "json",
+            path,
+        ])
+        .output()
+        .map_err(|e| format!("FFprobe execution error: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "FFprobe failed for {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let parsed: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("JSON parse error: {}", e))?;
+    let stream = &parsed["streams"][0];
+    // Unset colour fields are left for the encoder to default
+    let color = |key: &str| {
+        stream[key]
+            .as_str()
+            .filter(|v| *v != "unknown")
+            .map(str::to_string)
+    };
+
+    Ok(VideoStreamParams {
+        codec: stream["codec_name"].as_str().unwrap_or("").to_string(),
+        profile: stream["profile"].as_str().unwrap_or("").to_string(),
+        level: stream["level"].as_i64().unwrap_or(0),
+        width: stream["width"].as_u64().unwrap_or(0),
+        height: stream["height"].as_u64().unwrap_or(0),
+        pix_fmt: stream["pix_fmt"].as_str().unwrap_or("yuv420p").to_string(),
+        color_range: color("color_range"),
+        color_space: color("color_space"),
+        color_transfer: color("color_transfer"),
+        color_primaries: color("color_primaries"),
+        // "1/15360" -> "15360"
+        timescale: stream["time_base"]
+            .as_str()
+            .and_then(|tb| tb.split('/').nth(1))
+            .unwrap_or("90000")
+            .to_string(),
+    })
+}
+
+/// Keyframe timestamps of the first video stream between `in_sec` and
+/// `out_sec`, read from packet flags. Only that range is read; ffprobe
+/// starts from the keyframe before `in_sec`.
+fn probe_keyframes(path: &str, in_sec: f64, out_sec: f64) -> Result<Vec<f64>, String> {
+    let interval = format!("{}%{}", in_sec, out_sec + KEYFRAME_EPSILON);
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-read_intervals", &interval,
+            "-show_entries", "packet=pts_time,flags",
+            "-of", "csv=p=0",
+            path,
+        ])
+        .output()
+        .map_err(|e| format!("FFprobe execution error: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "FFprobe keyframe scan failed for {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let mut keyframes: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pts, flags) = line.split_once(',')?;
+            if flags.contains('K') {
+                pts.trim().parse::<f64>().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+    keyframes.sort_by(|a, b| a.total_cmp(b));
+    Ok(keyframes)
+}
+
+/// Split `in_sec..out_sec` into whole GOPs that can be copied and partial
+/// GOPs at either end that have to be re-encoded
+fn plan_clip(clip_index: usize, in_sec: f64, out_sec: f64, keyframes: &[f64]) -> Vec<RenderedSegment> {
+    let segment = |start_sec, end_sec, reencoded| RenderedSegment {
+        clip_index,
+        start_sec,
+        end_sec,
+        reencoded,
+    };
+
+    let first_key = keyframes
+        .iter()
+        .copied()
+        .find(|&k| k >= in_sec - KEYFRAME_EPSILON && k < out_sec);
+    let last_key = keyframes
+        .iter()
+        .copied()
+        .rev()
+        .find(|&k| k <= out_sec + KEYFRAME_EPSILON && k > in_sec);
+
+    let (copy_start, copy_end) = match (first_key, last_key) {
+        (Some(start), Some(end)) if end - start > KEYFRAME_EPSILON => (start, end.min(out_sec)),
+        _ => return vec![segment(in_sec, out_sec, true)],
+    };
+
+    let mut segments = Vec::new();
+    if copy_start - in_sec > KEYFRAME_EPSILON {
+        segments.push(segment(in_sec, copy_start, true));
+    }
+    segments.push(segment(copy_start, copy_end, false));
+    if out_sec - copy_end > KEYFRAME_EPSILON {
+        segments.push(segment(copy_end, out_sec, true));
+    }
+    segments
+}
+
+/// Export by stream-copying whole GOPs and re-encoding only the partial GOPs
+/// at each cut. Video keeps the source codec, profile, size and pixel format.
+/// Audio is rendered once for the whole timeline with the request's audio
+/// settings, so there are no gaps where segments meet.
+pub fn render(
+    request: &ExportRequest,
+    workspace: &Workspace,
+    ctx: &ExportContext,
+) -> Result<Vec<RenderedSegment>, String> {
+    let clips = &request.clips;
+    let settings = &request.settings;
+
+    // Every segment has to share codec parameters for the final -c copy join
+    let params = clips
+        .iter()
+        .map(|clip| probe_video_params(&clip.source_path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let reference = &params[0];
+    if params.iter().any(|p| p != reference) {
+        return Err("Smart render needs every clip to share codec, profile, resolution and pixel format".to_string());
+    }
+    let encoder_args = reference.encoder_args()?;
+    // The video is copied, so the codec setting has to be the source's
+    if reference.video_codec() != Some(settings.video_codec) || !settings.container.supports(settings.video_codec) {
+        return Err(format!(
+            "Smart render copies the source's {} video, so it can't write {:?} to {}; match the source codec or use a full re-encode",
+            reference.codec,
+            settings.video_codec,
+            settings.container.extension().to_uppercase()
+        ));
+    }
+
+    let streams = clips
+        .iter()
+        .map(|clip| video::probe_streams(&clip.source_path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.message)?;
+    let has_audio = streams.iter().any(|s| s.has_audio);
+
+    let mut segments = Vec::new();
+    for (i, clip) in clips.iter().enumerate() {
+        let keyframes = probe_keyframes(&clip.source_path, clip.in_sec, clip.out_sec)?;
+        segments.extend(plan_clip(i, clip.in_sec, clip.out_sec, &keyframes));
+    }
+
+    let mut stages: Vec<(String, f64)> = segments
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let action = if s.reencoded { "Re-encoding" } else { "Copying" };
+            (format!("{} segment {}/{}", action, i + 1, segments.len()), s.end_sec - s.start_sec)
+        })
+        .collect();
+    stages.push(("Rendering audio".to_string(), if has_audio { request.total_duration() } else { 0.0 }));
+    stages.push(("Joining segments".to_string(), request.total_duration()));
+    let audio_stage = stages.len() - 2;
+    let join_stage = stages.len() - 1;
+    let tracker = Mutex::new(ProgressTracker::new(stages));
+
+    // Segments are independent, so they are cut on the worker pool. They're
+    // MPEG-TS so parameter sets travel in-band and survive the join.
+    let workers = pool::worker_count(segments.len());
+    let threads = pool::threads_per_worker(workers).to_string();
+    let segment_files: Vec<PathBuf> = pool::run_ordered(&segments, workers, ctx.cancel, |i, segment| {
+        let clip = &clips[segment.clip_index];
+        let segment_file = workspace.path(&format!("segment_{}.ts", i));
+        println!(
+            "{} clip {} {:.3}s-{:.3}s",
+            if segment.reencoded { "🔁 Re-encoding" } else { "📋 Copying" },
+            segment.clip_index,
+            segment.start_sec,
+            segment.end_sec
+        );
+
+        let mut cmd = FfmpegCommand::new(&segment_file.to_string_lossy());
+        cmd.input(
+            Input::file(&clip.source_path)
+                .seek(segment.start_sec)
+                .duration(segment.end_sec - segment.start_sec),
+        );
+        cmd.map("0:v:0");
+        if segment.reencoded {
+            cmd.output_args(encoder_args.iter());
+        } else {
+            cmd.output_args(["-c:v".to_string(), "copy".to_string(), "-bsf:v".to_string(), reference.annexb_filter()]);
+        }
+        cmd.output_args(["-an", "-avoid_negative_ts", "make_zero", "-threads", &threads]);
+
+        ctx.run_stage(&cmd, &tracker, i)
+            .map_err(|e| format!("FFmpeg failed on segment {}: {}", i, e))?;
+
+        Ok(segment_file)
+    })?;
+
+    let audio_file = if has_audio {
+        // Encoded once in the codec the container takes, then copied in
+        let format = if settings.container.audio_encoder() == AudioFormat::Opus.encoder() {
+            AudioFormat::Opus
+        } else {
+            AudioFormat::M4a
+        };
+        let audio_settings = AudioSettings {
+            format,
+            bitrate_kbps: settings.audio_bitrate_kbps,
+            sample_rate: settings.audio_sample_rate,
+            channels: 2,
+            metadata: AudioMetadata::default(),
+        };
+        let audio_request = ExportRequest {
+            output_path: workspace.path(&format!("audio.{}", format.extension())).to_string_lossy().to_string(),
+            ..request.clone()
+        };
+        let cmd = audio::command(&audio_request, &streams, &audio_settings);
+        ctx.run_stage(&cmd, &tracker, audio_stage)
+            .map_err(|e| format!("FFmpeg audio render failed: {}", e))?;
+        Some(audio_request.output_path)
+    } else {
+        None
+    };
+
+    let concat_list = workspace.write_concat_list("concat_list.txt", &segment_files)?;
+
+    let mut cmd = FfmpegCommand::new(&request.output_path);
+    cmd.input(
+        Input::file(&concat_list.to_string_lossy())
+            .format("concat")
+            .option("-safe", "0"),
+    );
+    cmd.map("0:v:0");
+    if let Some(audio_file) = &audio_file {
+        let index = cmd.input(Input::file(audio_file));
+        cmd.map(&format!("{}:a:0", index));
+    }
+    cmd.output_args(["-c", "copy"]);
+    if matches!(settings.container, Container::Mp4 | Container::Mov) {
+        cmd.output_args(["-video_track_timescale", &reference.timescale, "-movflags", "+faststart"]);
+        if reference.codec == "hevc" {
+            cmd.output_args(["-tag:v", "hvc1"]);
+        }
+    }
+
+    ctx.run_stage(&cmd, &tracker, join_stage)
+        .map_err(|e| format!("FFmpeg concat failed: {}", e))?;
+
+    let reencoded = segments.iter().filter(|s| s.reencoded).count();
+    println!(
+        "✅ Smart render finished: {} of {} segments re-encoded",
+        reencoded,
+        segments.len()
+    );
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::settings::ExportMode;
+    use crate::export::test_support::*;
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn smart_render_copies_whole_gops() {
+        let dir = test_dir("smart");
+        let source = make_source(&dir, "src.mp4", 5.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&source, 0.5, 3.5)],
+            output_path: output.clone(),
+            mode: ExportMode::SmartRender,
+            ..ExportRequest::default()
+        };
+        let result = export(&request).unwrap();
+
+        let copied: Vec<_> = result.segments.iter().filter(|s| !s.reencoded).collect();
+        assert_eq!(copied.len(), 1);
+        assert!((copied[0].start_sec - 1.0).abs() < 0.01);
+        assert!((copied[0].end_sec - 3.0).abs() < 0.01);
+        assert_eq!(result.segments.iter().filter(|s| s.reencoded).count(), 2);
+        assert!((probe_duration(&output) - 3.0).abs() < 0.2);
+
+        // Re-encoded heads and tails decode with the copied GOPs' parameters
+        assert_eq!(decode_errors(&output), "");
+        let entries = "stream=profile,level,pix_fmt";
+        assert_eq!(probe_entries(&output, Some("v:0"), entries), probe_entries(&source, Some("v:0"), entries));
+        // One continuous audio track, not an AAC segment per cut
+        assert!((probe_entries(&output, Some("a:0"), "stream=duration").parse::<f64>().unwrap() - 3.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn clips_are_planned_as_copied_gops_and_reencoded_ends() {
+        let keyframes = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let spans = |segments: Vec<RenderedSegment>| -> Vec<(f64, f64, bool)> {
+            segments.iter().map(|s| (s.start_sec, s.end_sec, s.reencoded)).collect()
+        };
+
+        // Partial GOPs at both ends
+        assert_eq!(
+            spans(plan_clip(0, 0.5, 3.5, &keyframes)),
+            vec![(0.5, 1.0, true), (1.0, 3.0, false), (3.0, 3.5, true)]
+        );
+        // Cuts exactly on keyframes copy everything
+        assert_eq!(spans(plan_clip(1, 1.0, 3.0, &keyframes)), vec![(1.0, 3.0, false)]);
+        assert_eq!(plan_clip(1, 1.0, 3.0, &keyframes)[0].clip_index, 1);
+        // No keyframe inside the range
+        assert_eq!(spans(plan_clip(0, 1.2, 1.8, &keyframes)), vec![(1.2, 1.8, true)]);
+        // A single keyframe inside: no whole GOP to copy
+        assert_eq!(spans(plan_clip(0, 0.5, 3.5, &[0.0, 2.0, 4.0])), vec![(0.5, 3.5, true)]);
+    }
+
+    #[test]
+    fn reencoded_segments_match_the_source_bitstream() {
+        let params = VideoStreamParams {
+            codec: "h264".to_string(),
+            profile: "Constrained Baseline".to_string(),
+            level: 31,
+            width: 1280,
+            height: 720,
+            pix_fmt: "yuv420p".to_string(),
+            color_range: Some("tv".to_string()),
+            color_space: Some("bt709".to_string()),
+            color_transfer: None,
+            color_primaries: None,
+            timescale: "15360".to_string(),
+        };
+        let args = params.encoder_args().unwrap().join(" ");
+        assert!(args.contains("-c:v libx264 -profile:v baseline -level:v 3.1 -x264-params repeat-headers=1"));
+        assert!(args.contains("-pix_fmt yuv420p -color_range tv -colorspace bt709"));
+        assert!(!args.contains("-color_trc"));
+        assert_eq!(params.annexb_filter(), "h264_mp4toannexb");
+
+        let hevc = VideoStreamParams { codec: "hevc".to_string(), profile: "Main 10".to_string(), level: 93, ..params.clone() };
+        assert!(hevc.encoder_args().unwrap().join(" ").contains("-profile:v main10 -x265-params repeat-headers=1:level-idc=3.1"));
+        assert_eq!(hevc.video_codec(), Some(VideoCodec::H265));
+        let vp9 = VideoStreamParams { codec: "vp9".to_string(), ..params };
+        assert!(vp9.encoder_args().is_err());
+        assert_eq!(vp9.video_codec(), None);
+    }
+}
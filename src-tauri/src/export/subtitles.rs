@@ -0,0 +1,598 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::ffmpeg::{FfmpegCommand, Input};
+use super::settings::Container;
+use super::text::escape_filter_value;
+use super::ClipData;
+use crate::transcription::TranscriptSegment;
+
+/// Cues shorter than this after trimming are dropped
+const MIN_CUE_SEC: f64 = 0.05;
+
+/// Lines shown at once; longer cues are split over time
+const MAX_LINES_PER_CUE: usize = 2;
+
+/// Vertical placement of burned-in captions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitlePosition {
+    #[default]
+    Bottom,
+    Middle,
+    Top,
+}
+
+impl SubtitlePosition {
+    /// ASS numpad alignment, horizontally centered
+    fn ass_alignment(self) -> u8 {
+        match self {
+            SubtitlePosition::Bottom => 2,
+            SubtitlePosition::Middle => 5,
+            SubtitlePosition::Top => 8,
+        }
+    }
+}
+
+/// How burned-in captions look
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubtitleStyle {
+    pub font_name: String,
+    /// Directory with extra fonts for `font_name`; system fonts otherwise
+    pub fonts_dir: Option<String>,
+    /// In pixels of the output frame
+    pub font_size: u32,
+    /// `#RRGGBB`
+    pub color: String,
+    pub outline_color: String,
+    pub outline: f64,
+    pub position: SubtitlePosition,
+    pub margin_v: u32,
+    pub max_chars_per_line: usize,
+}
+
+impl Default for SubtitleStyle {
+    fn default() -> Self {
+        SubtitleStyle {
+            font_name: "Arial".to_string(),
+            fonts_dir: None,
+            font_size: 42,
+            color: "#FFFFFF".to_string(),
+            outline_color: "#000000".to_string(),
+            outline: 2.0,
+            position: SubtitlePosition::Bottom,
+            margin_v: 40,
+            max_chars_per_line: 42,
+        }
+    }
+}
+
+impl SubtitleStyle {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.font_size == 0 {
+            return Err("Subtitle font size must be positive".to_string());
+        }
+        if self.max_chars_per_line < 10 {
+            return Err("Subtitle lines need room for at least 10 characters".to_string());
+        }
+        if self.outline < 0.0 {
+            return Err("Subtitle outline can't be negative".to_string());
+        }
+        ass_color(&self.color)?;
+        ass_color(&self.outline_color)?;
+        if let Some(dir) = &self.fonts_dir {
+            if !Path::new(dir).is_dir() {
+                return Err(format!("Fonts directory not found: {}", dir));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Transcript captions to burn into the export
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BurnInSubtitles {
+    /// Transcript segments per source file path, in source time
+    pub transcripts: HashMap<String, Vec<TranscriptSegment>>,
+    #[serde(default)]
+    pub style: SubtitleStyle,
+}
+
+/// A caption on the output timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_sec: f64,
+    pub end_sec: f64,
+    pub text: String,
+}
+
+/// Move transcript segments from source time onto the output timeline.
+/// `clip_starts[i]` is where clip `i` begins in the output; segments are
+/// clipped to each clip's `in_sec..out_sec` range and follow its speed and
+/// direction, and a source used by several clips contributes captions to
+/// each of them. Freeze frames get no captions.
+pub fn remap_segments(
+    clips: &[ClipData],
+    clip_starts: &[f64],
+    transcripts: &HashMap<String, Vec<TranscriptSegment>>,
+) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    for (clip, &clip_start) in clips.iter().zip(clip_starts) {
+        let Some(segments) = transcripts.get(&clip.source_path) else { continue };
+        for segment in segments {
+            let start = segment.start_sec.max(clip.in_sec);
+            let end = segment.end_sec.min(clip.out_sec);
+            if end - start < MIN_CUE_SEC || segment.text.trim().is_empty() {
+                continue;
+            }
+            let (Some(a), Some(b)) = (clip.source_to_clip_time(start), clip.source_to_clip_time(end)) else {
+                continue;
+            };
+            // Reversed clips play the segment's end first
+            let (start, end) = (a.min(b), a.max(b));
+            if end - start < MIN_CUE_SEC {
+                continue;
+            }
+            cues.push(Cue {
+                start_sec: clip_start + start,
+                end_sec: clip_start + end,
+                text: segment.text.trim().to_string(),
+            });
+        }
+    }
+    cues.sort_by(|a, b| a.start_sec.total_cmp(&b.start_sec));
+    cues
+}
+
+/// Greedy word wrap; words longer than a line get a line of their own
+fn wrap_words(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Wrap cue text to `max_chars` per line. Cues that need more than two lines
+/// are split into consecutive cues, sharing the time by text length.
+pub fn wrap_cues(cues: &[Cue], max_chars: usize) -> Vec<Cue> {
+    let mut wrapped = Vec::new();
+    for cue in cues {
+        let lines = wrap_words(&cue.text, max_chars);
+        let chunks: Vec<&[String]> = lines.chunks(MAX_LINES_PER_CUE).collect();
+        let total_chars: usize = lines.iter().map(|l| l.chars().count()).sum();
+        let duration = cue.end_sec - cue.start_sec;
+
+        let mut start = cue.start_sec;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let chars: usize = chunk.iter().map(|l| l.chars().count()).sum();
+            let end = if i == chunks.len() - 1 {
+                cue.end_sec
+            } else {
+                start + duration * chars as f64 / total_chars.max(1) as f64
+            };
+            wrapped.push(Cue {
+                start_sec: start,
+                end_sec: end,
+                text: chunk.join("\n"),
+            });
+            start = end;
+        }
+    }
+    wrapped
+}
+
+fn srt_timestamp(sec: f64) -> String {
+    let ms = (sec.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+fn ass_timestamp(sec: f64) -> String {
+    let cs = (sec.max(0.0) * 100.0).round() as u64;
+    format!("{}:{:02}:{:02}.{:02}", cs / 360_000, cs / 6000 % 60, cs / 100 % 60, cs % 100)
+}
+
+/// `#RRGGBB` -> ASS `&HAABBGGRR` with full opacity
+fn ass_color(hex: &str) -> Result<String, String> {
+    let digits = hex.trim_start_matches('#');
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid subtitle color {} (expected #RRGGBB)", hex));
+    }
+    let (r, g, b) = (&digits[0..2], &digits[2..4], &digits[4..6]);
+    Ok(format!("&H00{}{}{}", b, g, r).to_uppercase())
+}
+
+/// Keep caption text from being read as ASS override tags or line breaks
+fn escape_ass_text(text: &str) -> String {
+    text.replace('\\', "\u{29F5}")
+        .replace('{', "(")
+        .replace('}', ")")
+        .replace('\n', "\\N")
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    cues.iter()
+        .enumerate()
+        .map(|(i, cue)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                srt_timestamp(cue.start_sec),
+                srt_timestamp(cue.end_sec),
+                cue.text
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// ASS script for the cues. The script resolution matches the output frame so
+/// font size and margins are in output pixels.
+pub fn to_ass(cues: &[Cue], style: &SubtitleStyle, width: u32, height: u32) -> Result<String, String> {
+    let mut ass = format!(
+        "[Script Info]\nScriptType: v4.00+\nPlayResX: {}\nPlayResY: {}\nWrapStyle: 2\nScaledBorderAndShadow: yes\n\n",
+        width, height
+    );
+    ass.push_str("[V4+ Styles]\n");
+    ass.push_str("Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n");
+    ass.push_str(&format!(
+        "Style: Default,{},{},{},{},{},&H80000000,0,0,0,0,100,100,0,0,1,{},0,{},20,20,{},1\n\n",
+        style.font_name.replace(',', " "),
+        style.font_size,
+        ass_color(&style.color)?,
+        ass_color(&style.color)?,
+        ass_color(&style.outline_color)?,
+        style.outline,
+        style.position.ass_alignment(),
+        style.margin_v
+    ));
+    ass.push_str("[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n");
+    for cue in cues {
+        ass.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,,{}\n",
+            ass_timestamp(cue.start_sec),
+            ass_timestamp(cue.end_sec),
+            escape_ass_text(&cue.text)
+        ));
+    }
+    Ok(ass)
+}
+
+/// `subtitles` filter that burns the ASS file at `ass_path` into the video
+pub fn burn_in_filter(ass_path: &Path, style: &SubtitleStyle) -> String {
+    let mut filter = format!(
+        "subtitles=filename={}",
+        escape_filter_value(&ass_path.to_string_lossy())
+    );
+    if let Some(dir) = &style.fonts_dir {
+        filter.push_str(&format!(":fontsdir={}", escape_filter_value(dir)));
+    }
+    filter
+}
+
+/// Selectable subtitle track muxed into the export
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubtitleTrack {
+    /// Transcript segments per source file path, in source time
+    #[serde(default)]
+    pub transcripts: HashMap<String, Vec<TranscriptSegment>>,
+    /// Imported .srt/.vtt files per source file path, timed to that source
+    #[serde(default)]
+    pub files: HashMap<String, String>,
+    /// ISO 639-2 code, e.g. "eng"
+    pub language: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub default: bool,
+    #[serde(default)]
+    pub forced: bool,
+    /// MKV only: write the track as styled ASS instead of SRT
+    #[serde(default)]
+    pub style: Option<SubtitleStyle>,
+}
+
+impl SubtitleTrack {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.language.len() != 3 || !self.language.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(format!(
+                "Subtitle language must be a 3-letter ISO 639-2 code like \"eng\", got \"{}\"",
+                self.language
+            ));
+        }
+        if self.transcripts.is_empty() && self.files.is_empty() {
+            return Err(format!("Subtitle track \"{}\" has no captions", self.language));
+        }
+        for path in self.files.values() {
+            if !Path::new(path).is_file() {
+                return Err(format!("Subtitle file not found: {}", path));
+            }
+        }
+        if let Some(style) = &self.style {
+            style.validate()?;
+        }
+        Ok(())
+    }
+
+    /// All captions of the track in source time: transcripts plus parsed files
+    fn segments(&self) -> Result<HashMap<String, Vec<TranscriptSegment>>, String> {
+        let mut segments = self.transcripts.clone();
+        for (source, path) in &self.files {
+            segments
+                .entry(source.clone())
+                .or_default()
+                .extend(parse_subtitle_file(path)?);
+        }
+        Ok(segments)
+    }
+
+    /// Write the track for `container` on the output timeline and return the
+    /// file. `frame_size` lays out styled ASS tracks in output pixels.
+    pub fn write(
+        &self,
+        clips: &[ClipData],
+        clip_starts: &[f64],
+        container: Container,
+        frame_size: (u32, u32),
+        path_without_extension: &Path,
+    ) -> Result<std::path::PathBuf, String> {
+        let cues = remap_segments(clips, clip_starts, &self.segments()?);
+        let (content, extension) = match (&self.style, container) {
+            (Some(style), Container::Mkv) => (to_ass(&cues, style, frame_size.0, frame_size.1)?, "ass"),
+            _ => (to_srt(&cues), "srt"),
+        };
+        let path = path_without_extension.with_extension(extension);
+        std::fs::write(&path, content).map_err(|e| format!("Failed to write subtitle track: {}", e))?;
+        Ok(path)
+    }
+}
+
+/// Subtitle codec each container can carry
+fn subtitle_codec(container: Container, styled: bool) -> &'static str {
+    match container {
+        Container::Mp4 | Container::Mov => "mov_text",
+        Container::Webm => "webvtt",
+        Container::Mkv if styled => "ass",
+        Container::Mkv => "srt",
+    }
+}
+
+/// Copy `rendered` to `output_path` and add one subtitle stream per track file
+pub fn mux_command(
+    rendered: &Path,
+    tracks: &[(&SubtitleTrack, std::path::PathBuf)],
+    container: Container,
+    output_path: &str,
+) -> FfmpegCommand {
+    let mut cmd = FfmpegCommand::new(output_path);
+    cmd.input(Input::file(&rendered.to_string_lossy()));
+    cmd.map("0:v?").map("0:a?");
+    cmd.output_args(["-c:v", "copy", "-c:a", "copy"]);
+
+    for (i, (track, file)) in tracks.iter().enumerate() {
+        let input = cmd.input(Input::file(&file.to_string_lossy()));
+        cmd.map(&input.to_string());
+
+        let stream = format!("s:{}", i);
+        let styled = track.style.is_some();
+        cmd.output_args([format!("-c:{}", stream), subtitle_codec(container, styled).to_string()]);
+        // -metadata takes `s:<stream specifier>`, -c and -disposition take the specifier directly
+        cmd.output_args([format!("-metadata:s:{}", stream), format!("language={}", track.language)]);
+        if let Some(title) = &track.title {
+            cmd.output_args([format!("-metadata:s:{}", stream), format!("title={}", title)]);
+        }
+        let disposition = match (track.default, track.forced) {
+            (true, true) => "default+forced",
+            (true, false) => "default",
+            (false, true) => "forced",
+            (false, false) => "0",
+        };
+        cmd.output_args([format!("-disposition:{}", stream), disposition.to_string()]);
+    }
+
+    if matches!(container, Container::Mp4 | Container::Mov) {
+        cmd.output_args(["-movflags", "+faststart"]);
+    }
+    cmd
+}
+
+/// `HH:MM:SS,mmm` (SRT) or `[HH:]MM:SS.mmm` (WebVTT) to seconds
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', ".");
+    let parts: Vec<&str> = value.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (h.parse::<f64>().ok()?, m.parse::<f64>().ok()?, s.parse::<f64>().ok()?),
+        [m, s] => (0.0, m.parse::<f64>().ok()?, s.parse::<f64>().ok()?),
+        _ => return None,
+    };
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Drop WebVTT/SRT markup such as `<i>` or `<c.yellow>`
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// Parse SRT or WebVTT text into timed segments. Blocks without a
+/// `start --> end` line (headers, NOTE and STYLE blocks) are skipped.
+pub fn parse_subtitles(content: &str) -> Vec<TranscriptSegment> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut segments = Vec::new();
+
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| !l.contains("-->"));
+        let Some(timing) = lines.next() else { continue };
+        let Some((start, rest)) = timing.split_once("-->") else { continue };
+        // WebVTT cue settings follow the end time
+        let end = rest.split_whitespace().next().unwrap_or("");
+        let (Some(start_sec), Some(end_sec)) = (parse_timestamp(start), parse_timestamp(end)) else {
+            continue;
+        };
+
+        let text = lines.map(strip_tags).collect::<Vec<String>>().join("\n");
+        if !text.trim().is_empty() && end_sec > start_sec {
+            segments.push(TranscriptSegment {
+                start_sec,
+                end_sec,
+                text: text.trim().to_string(),
+            });
+        }
+    }
+    segments
+}
+
+pub fn parse_subtitle_file(path: &str) -> Result<Vec<TranscriptSegment>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read subtitle file {}: {}", path, e))?;
+    Ok(parse_subtitles(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{clip_start_times, settings, transition, ExportRequest};
+    use std::path::PathBuf;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn transcript_segments_follow_trims_onto_the_timeline() {
+        use crate::transcription::TranscriptSegment;
+        let segment = |start_sec, end_sec, text: &str| TranscriptSegment {
+            start_sec,
+            end_sec,
+            text: text.to_string(),
+        };
+        let mut transcripts = std::collections::HashMap::new();
+        transcripts.insert(
+            "a.mp4".to_string(),
+            vec![segment(0.0, 2.0, "cut away"), segment(4.0, 6.0, "kept"), segment(9.0, 11.0, "half")],
+        );
+
+        // a.mp4 4..10 plays first, then a.mp4 0..1 after a 0.5s crossfade
+        let clips = vec![
+            with_transition(clip("a.mp4", 4.0, 10.0), transition::TransitionKind::Crossfade, 0.5),
+            clip("a.mp4", 0.0, 1.0),
+        ];
+        let cues = remap_segments(&clips, &clip_start_times(&clips), &transcripts);
+        let timed: Vec<(f64, f64, &str)> =
+            cues.iter().map(|c| (c.start_sec, c.end_sec, c.text.as_str())).collect();
+        assert_eq!(
+            timed,
+            vec![(0.0, 2.0, "kept"), (5.0, 6.0, "half"), (5.5, 6.5, "cut away")]
+        );
+    }
+
+    #[test]
+    fn long_cues_are_wrapped_and_split() {
+        let cue = Cue {
+            start_sec: 0.0,
+            end_sec: 4.0,
+            text: "one two three four five six seven eight nine ten".to_string(),
+        };
+        let wrapped = wrap_cues(&[cue], 14);
+        let texts: Vec<&str> = wrapped.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["one two three\nfour five six", "seven eight\nnine ten"]);
+        assert_eq!(wrapped[0].start_sec, 0.0);
+        assert_eq!(wrapped[1].end_sec, 4.0);
+        assert!(wrapped[0].end_sec > 2.0 && wrapped[0].end_sec < 3.0);
+
+        let srt = to_srt(&wrapped);
+        assert!(srt.starts_with("1\n00:00:00,000 --> 00:00:0"));
+        let ass = to_ass(&wrapped, &SubtitleStyle::default(), 1280, 720).unwrap();
+        assert!(ass.contains("PlayResX: 1280\nPlayResY: 720"));
+        assert!(ass.contains(",Default,,0,0,0,,seven eight\\Nnine ten\n"));
+    }
+
+    #[test]
+    fn srt_and_webvtt_files_are_parsed() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\nHello <i>there</i>\r\n\r\n2\r\n00:01:00,000 --> 00:01:01,000\r\nTwo\r\nlines\r\n";
+        let cues = parse_subtitles(srt);
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start_sec, cues[0].end_sec), (1.0, 2.5));
+        assert_eq!(cues[0].text, "Hello there");
+        assert_eq!(cues[1].text, "Two\nlines");
+
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nintro\n00:05.000 --> 00:06.250 align:start\n<c.yellow>Hi</c>\n";
+        let cues = parse_subtitles(vtt);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_sec, cues[0].end_sec), (5.0, 6.25));
+        assert_eq!(cues[0].text, "Hi");
+    }
+
+    #[test]
+    fn subtitle_tracks_are_muxed_with_language_and_disposition() {
+        let track = SubtitleTrack {
+            language: "spa".to_string(),
+            title: Some("Español".to_string()),
+            default: true,
+            forced: true,
+            ..SubtitleTrack::default()
+        };
+        let tracks = vec![(&track, PathBuf::from("subs.srt"))];
+        let cmd = mux_command(Path::new("render.mp4"), &tracks, settings::Container::Mp4, "out.mp4");
+        let args = cmd.to_args().join(" ");
+        assert!(args.contains("-i subs.srt -map 0:v? -map 0:a? -map 1"));
+        assert!(args.contains("-c:s:0 mov_text -metadata:s:s:0 language=spa -metadata:s:s:0 title=Español"));
+        assert!(args.contains("-disposition:s:0 default+forced"));
+
+        let webm = mux_command(Path::new("render.webm"), &tracks, settings::Container::Webm, "out.webm");
+        assert!(webm.to_args().join(" ").contains("-c:s:0 webvtt"));
+
+        let bad_language = SubtitleTrack { language: "en".to_string(), ..track.clone() };
+        assert!(bad_language.validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn soft_subtitles_are_remapped_and_muxed() {
+        let dir = test_dir("soft_subs");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let srt = dir.join("src.srt");
+        std::fs::write(&srt, "1\n00:00:02,000 --> 00:00:03,000\nSecond half\n").unwrap();
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let mut files = std::collections::HashMap::new();
+        files.insert(source.clone(), srt.to_string_lossy().to_string());
+        let request = ExportRequest {
+            clips: vec![clip(&source, 1.5, 4.0)],
+            output_path: output.clone(),
+            subtitle_tracks: vec![SubtitleTrack {
+                files,
+                language: "eng".to_string(),
+                default: true,
+                ..SubtitleTrack::default()
+            }],
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert_eq!(probe_entries(&output, Some("s"), "stream=codec_name:stream_tags=language"), "mov_text,eng");
+
+        // The cue at 2s in the source lands at 0.5s on the timeline
+        let packets = probe_entries(&output, Some("s"), "packet=pts_time");
+        let first: f64 = packets.lines().next().unwrap().trim().parse().unwrap();
+        assert!((first - 0.5).abs() < 0.05);
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Mutex;
+
+use super::conform::OutputFormat;
+use super::ffmpeg::{null_output, FfmpegCommand, Input};
+use super::loudness;
+use super::progress::ProgressTracker;
+use super::settings::{Container, ExportSettings, QualityMode, Resolution, VideoCodec};
+use super::workspace::Workspace;
+use super::{ExportContext, ExportRequest, ExportResult};
+use crate::video;
+
+/// Share of the file taken by container headers and indexes
+const MUX_OVERHEAD: f64 = 0.02;
+/// Below this many bits per pixel per frame the picture falls apart
+const MIN_BITS_PER_PIXEL: f64 = 0.01;
+/// Floor for tiny frame sizes, where the bits-per-pixel rule gets too low
+const MIN_VIDEO_KBPS: f64 = 100.0;
+
+/// Video bitrate that makes `duration_sec` of video plus the audio fit in `size_bytes`
+pub fn video_bitrate_kbps(size_bytes: u64, duration_sec: f64, audio_kbps: u32) -> f64 {
+    let total_kbits = size_bytes as f64 * 8.0 / 1000.0 * (1.0 - MUX_OVERHEAD);
+    total_kbits / duration_sec - audio_kbps as f64
+}
+
+/// Lowest video bitrate we'll encode at for this frame size and rate
+pub fn minimum_video_kbps(frame: Resolution, fps: f64) -> f64 {
+    let kbps = frame.width as f64 * frame.height as f64 * fps * MIN_BITS_PER_PIXEL / 1000.0;
+    kbps.max(MIN_VIDEO_KBPS)
+}
+
+/// Work out the video bitrate for the request, or explain why the target can't be met
+fn plan_bitrate(request: &ExportRequest, size_bytes: u64) -> Result<u32, String> {
+    let settings = &request.settings;
+    let duration = request.total_duration();
+
+    let sources = request
+        .clips
+        .iter()
+        .map(|clip| video::probe_streams(&clip.source_path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.message)?;
+    let OutputFormat { frame, fps, .. } = OutputFormat::choose(&request.clips, &sources, settings);
+
+    let kbps = video_bitrate_kbps(size_bytes, duration, settings.audio_bitrate_kbps);
+    let minimum = minimum_video_kbps(frame, fps);
+    if kbps < minimum {
+        let needed_bytes = (minimum + settings.audio_bitrate_kbps as f64) * 1000.0 / 8.0 * duration
+            / (1.0 - MUX_OVERHEAD);
+        return Err(format!(
+            "{:.1}s of {}x{} video won't fit in {:.1} MB: that leaves {:.0} kbps for video, and the minimum is {:.0} kbps. \
+             Aim for at least {:.1} MB, lower the resolution or audio bitrate, or shorten the timeline.",
+            duration,
+            frame.width,
+            frame.height,
+            size_bytes as f64 / 1_000_000.0,
+            kbps.max(0.0),
+            minimum,
+            needed_bytes / 1_000_000.0
+        ));
+    }
+    Ok(kbps.floor() as u32)
+}
+
+/// Whether the intermediate's audio can go into the output as is: it's
+/// encoded once at the output's bitrate, unless the output needs another
+/// codec or loudness normalization
+fn copies_audio(settings: &ExportSettings) -> bool {
+    settings.container.audio_encoder() == Container::Mkv.audio_encoder() && settings.loudness.is_none()
+}
+
+/// Second pass: encode the intermediate's video at the planned bitrate, and
+/// copy its audio when it's already in the output's codec. `audio_filter`
+/// (the loudness correction) means the audio is re-encoded through it.
+fn second_pass(
+    settings: &ExportSettings,
+    intermediate: &str,
+    passlog: &str,
+    output_path: &str,
+    audio_filter: Option<&str>,
+) -> FfmpegCommand {
+    let mut cmd = FfmpegCommand::new(output_path);
+    cmd.input(Input::file(intermediate));
+    cmd.output_args(settings.video_codec_args());
+    match audio_filter {
+        Some(filter) => {
+            cmd.output_args(["-af", filter]).output_args(settings.audio_codec_args());
+        }
+        None if copies_audio(settings) => {
+            cmd.output_args(["-c:a", "copy"]);
+        }
+        None => {
+            cmd.output_args(settings.audio_codec_args());
+        }
+    }
+    cmd.output_args(settings.muxer_args())
+        .output_args(["-pass", "2", "-passlogfile", passlog]);
+    cmd
+}
+
+/// Render the timeline to a high quality intermediate, then encode it twice:
+/// once to measure, once at the bitrate that hits the target size. Loudness
+/// is measured on the intermediate and corrected in the second pass, so the
+/// audio isn't encoded again after the size was planned around it.
+pub fn render(
+    request: &ExportRequest,
+    size_bytes: u64,
+    workspace: &Workspace,
+    ctx: &ExportContext,
+) -> Result<ExportResult, String> {
+    // Fail before spending minutes on the intermediate
+    let kbps = plan_bitrate(request, size_bytes)?;
+    println!(
+        "🎯 Target {} bytes: encoding video at {} kbps in two passes",
+        size_bytes, kbps
+    );
+
+    let intermediate = workspace.path("timeline.mkv");
+    let timeline_request = ExportRequest {
+        output_path: intermediate.to_string_lossy().to_string(),
+        settings: ExportSettings {
+            video_codec: VideoCodec::H264,
+            quality: QualityMode::Crf { crf: 12 },
+            container: Container::Mkv,
+            // Only re-encoded audio needs the headroom
+            audio_bitrate_kbps: if copies_audio(&request.settings) { request.settings.audio_bitrate_kbps } else { 320 },
+            ..request.settings.clone()
+        },
+        ..request.clone()
+    };
+    let mut result = super::render(&timeline_request, workspace, ctx)?;
+    let intermediate = intermediate.to_string_lossy().to_string();
+
+    let settings = ExportSettings {
+        quality: QualityMode::Bitrate { kbps },
+        ..request.settings.clone()
+    };
+    let passlog = workspace.path("passlog").to_string_lossy().to_string();
+    let duration = request.total_duration();
+    let tracker = Mutex::new(ProgressTracker::new(vec![
+        (
+            "Measuring loudness".to_string(),
+            if settings.loudness.is_some() { duration } else { 0.0 },
+        ),
+        ("Analysing video (pass 1 of 2)".to_string(), duration),
+        ("Encoding to target size (pass 2 of 2)".to_string(), duration),
+    ]));
+
+    let measured = match &settings.loudness {
+        Some(target) => loudness::measure(&intermediate, target, &tracker, 0, ctx)?
+            .map(|measured| (loudness::correction_filter(target, &measured), measured)),
+        None => None,
+    };
+
+    let mut cmd = FfmpegCommand::new(null_output());
+    cmd.input(Input::file(&intermediate));
+    cmd.output_args(settings.video_codec_args())
+        .output_args(["-pass", "1", "-passlogfile", &passlog, "-an", "-f", "null"]);
+    ctx.run_stage(&cmd, &tracker, 1)
+        .map_err(|e| format!("FFmpeg first pass failed: {}", e))?;
+
+    let audio_filter = measured.as_ref().map(|(filter, _)| filter.as_str());
+    let cmd = second_pass(&settings, &intermediate, &passlog, &request.output_path, audio_filter);
+    let log = ctx
+        .run_stage_with_log(&cmd, &tracker, 2)
+        .map_err(|e| format!("FFmpeg second pass failed: {}", e))?;
+
+    if let Some((_, measured)) = &measured {
+        result.loudness = Some(loudness::report(measured, &log)?);
+    }
+    result.video_bitrate_kbps = Some(kbps);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::settings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn target_size_bitrate_leaves_room_for_audio_and_overhead() {
+        // 25 MB over 100s with 128k audio: 1960 kbps total, 1832 for video
+        let kbps = video_bitrate_kbps(25_000_000, 100.0, 128);
+        assert!((kbps - 1832.0).abs() < 0.5);
+
+        let hd = settings::Resolution { width: 1920, height: 1080 };
+        assert!((minimum_video_kbps(hd, 30.0) - 622.08).abs() < 0.01);
+        let tiny = settings::Resolution { width: 160, height: 120 };
+        assert_eq!(minimum_video_kbps(tiny, 10.0), 100.0);
+
+        let settings = ExportSettings {
+            quality: QualityMode::TargetSize { size_bytes: 25_000_000 },
+            video_codec: settings::VideoCodec::H265,
+            ..ExportSettings::default()
+        };
+        assert!(settings.validate().is_err());
+
+        // AAC from the intermediate is copied; WebM needs it as Opus
+        let mp4 = ExportSettings { quality: QualityMode::Bitrate { kbps: 1832 }, ..ExportSettings::default() };
+        let args = second_pass(&mp4, "timeline.mkv", "passlog", "out.mp4", None).to_args().join(" ");
+        assert!(args.contains("-b:v 1832k"));
+        assert!(args.contains("-c:a copy -movflags +faststart -pass 2 -passlogfile passlog out.mp4"));
+        let webm = ExportSettings {
+            container: Container::Webm,
+            video_codec: VideoCodec::Vp9,
+            ..mp4
+        };
+        let args = second_pass(&webm, "timeline.mkv", "passlog", "out.webm", None).to_args().join(" ");
+        assert!(args.contains("-c:a libopus") && !args.contains("-c:a copy"));
+
+        // Loudness is corrected in the second pass rather than in another encode
+        let normalized = ExportSettings {
+            loudness: Some(settings::LoudnessTarget { integrated_lufs: -16.0, true_peak_dbtp: -1.5, loudness_range_lu: 11.0 }),
+            ..mp4
+        };
+        assert!(!copies_audio(&normalized));
+        let args = second_pass(&normalized, "timeline.mkv", "passlog", "out.mp4", Some("loudnorm=I=-16")).to_args().join(" ");
+        assert!(args.contains("-af loudnorm=I=-16 -c:a aac -b:a 128k"));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn target_size_export_stays_under_the_limit() {
+        let dir = test_dir("target_size");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let mut request = ExportRequest {
+            clips: vec![clip(&source, 0.0, 4.0)],
+            output_path: output.clone(),
+            settings: ExportSettings {
+                quality: QualityMode::TargetSize { size_bytes: 200_000 },
+                audio_bitrate_kbps: 64,
+                ..ExportSettings::default()
+            },
+            ..ExportRequest::default()
+        };
+        let result = export(&request).unwrap();
+        assert!(result.video_bitrate_kbps.is_some());
+        assert!(std::fs::metadata(&output).unwrap().len() <= 210_000);
+
+        // 4s of audio alone is more than 20 KB
+        request.settings.quality = QualityMode::TargetSize { size_bytes: 20_000 };
+        let error = export(&request).unwrap_err();
+        assert!(error.contains("won't fit"));
+    }
+}
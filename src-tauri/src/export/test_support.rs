@@ -0,0 +1,110 @@
+//! Helpers shared by the export tests. Tests that render real files are
+//! `#[ignore]`d because they need ffmpeg and ffprobe on the PATH; run them
+//! with `cargo test -- --ignored`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::ffmpeg::{CancelToken, FfmpegCommand, Input};
+use super::transition::{Transition, TransitionKind};
+use super::{run, ClipData, ExportContext, ExportRequest, ExportResult};
+use crate::video::StreamInfo;
+
+/// Render a synthetic test source with lavfi
+pub fn make_source(dir: &Path, name: &str, duration: f64, size: &str, audio: bool) -> String {
+    let path = dir.join(name);
+    let mut cmd = FfmpegCommand::new(&path.to_string_lossy());
+    cmd.input(
+        Input::file(&format!("testsrc=duration={}:size={}:rate=25", duration, size))
+            .format("lavfi"),
+    );
+    if audio {
+        cmd.input(
+            Input::file(&format!("sine=frequency=440:duration={}", duration)).format("lavfi"),
+        );
+    }
+    // Fixed GOP: a keyframe exactly every second
+    cmd.output_args(["-c:v", "libx264", "-g", "25", "-sc_threshold", "0", "-pix_fmt", "yuv420p"]);
+    if audio {
+        cmd.output_args(["-c:a", "aac"]);
+    }
+    cmd.run(&CancelToken::default(), |_| {}).expect("failed to render test source");
+    path.to_string_lossy().to_string()
+}
+
+pub fn probe_duration(path: &str) -> f64 {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0", path])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).trim().parse().unwrap()
+}
+
+/// `ffprobe -show_entries` output for `path`, in csv without headers
+pub fn probe_entries(path: &str, select: Option<&str>, entries: &str) -> String {
+    let mut args = vec!["-v", "error"];
+    if let Some(streams) = select {
+        args.extend(["-select_streams", streams]);
+    }
+    args.extend(["-show_entries", entries, "-of", "csv=p=0", path]);
+    let output = Command::new("ffprobe").args(args).output().unwrap();
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Everything ffmpeg complains about while decoding `path`; empty for a clean file
+pub fn decode_errors(path: &str) -> String {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i", path, "-f", "null", "-"])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+pub fn clip(path: &str, in_sec: f64, out_sec: f64) -> ClipData {
+    ClipData {
+        source_path: path.to_string(),
+        in_sec,
+        out_sec,
+        transition: None,
+        speed: 1.0,
+        reverse: false,
+        freeze: None,
+        transform: None,
+        volume: None,
+        cleanup: None,
+    }
+}
+
+pub fn with_transition(mut clip: ClipData, kind: TransitionKind, duration_sec: f64) -> ClipData {
+    clip.transition = Some(Transition {
+        kind,
+        duration_sec,
+        direction: Default::default(),
+    });
+    clip
+}
+
+/// A constant frame rate source with video and audio
+pub fn stream(width: u32, height: u32, frame_rate: f64) -> StreamInfo {
+    StreamInfo {
+        has_video: true,
+        has_audio: true,
+        width,
+        height,
+        frame_rate,
+        variable_frame_rate: false,
+    }
+}
+
+pub fn export(request: &ExportRequest) -> Result<ExportResult, String> {
+    let cancel = CancelToken::default();
+    run(request, &ExportContext { cancel: &cancel, on_progress: &|_| {} })
+}
+
+/// Empty scratch directory for one test
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clipforge_test_{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
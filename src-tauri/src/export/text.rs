@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+
+/// Where a text overlay sits in the frame
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TextPosition {
+    Top,
+    Center,
+    Bottom,
+    /// Left-aligned, two thirds of the way down
+    LowerThird,
+    /// Top-left corner of the text as a fraction of the frame size (0..1)
+    Custom { x: f64, y: f64 },
+}
+
+impl TextPosition {
+    /// drawtext `x` and `y` expressions, keeping 5% away from the frame edges
+    fn expressions(&self) -> (String, String) {
+        let center_x = "(w-text_w)/2".to_string();
+        match self {
+            TextPosition::Top => (center_x, "h*0.05".to_string()),
+            TextPosition::Center => (center_x, "(h-text_h)/2".to_string()),
+            TextPosition::Bottom => (center_x, "h-text_h-h*0.05".to_string()),
+            TextPosition::LowerThird => ("w*0.05".to_string(), "h*2/3".to_string()),
+            TextPosition::Custom { x, y } => (format!("w*{}", x), format!("h*{}", y)),
+        }
+    }
+}
+
+/// Filled box drawn behind the text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextBackground {
+    pub color: String,
+    #[serde(default = "default_box_opacity")]
+    pub opacity: f64,
+    #[serde(default = "default_box_padding")]
+    pub padding: u32,
+}
+
+fn default_box_opacity() -> f64 {
+    0.6
+}
+
+fn default_box_padding() -> u32 {
+    12
+}
+
+/// Title, lower third or callout drawn over the exported video. Times are
+/// on the output timeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextOverlay {
+    pub text: String,
+    /// Path to a .ttf/.otf file; ffmpeg's default font when unset
+    #[serde(default)]
+    pub font_file: Option<String>,
+    #[serde(default = "default_font_size")]
+    pub font_size: u32,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default)]
+    pub background: Option<TextBackground>,
+    pub position: TextPosition,
+    pub start_sec: f64,
+    pub end_sec: f64,
+    #[serde(default)]
+    pub fade_in_sec: f64,
+    #[serde(default)]
+    pub fade_out_sec: f64,
+}
+
+fn default_font_size() -> u32 {
+    48
+}
+
+fn default_color() -> String {
+    "white".to_string()
+}
+
+impl TextOverlay {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() {
+            return Err("Text overlay has no text".to_string());
+        }
+        if self.start_sec < 0.0 || self.end_sec <= self.start_sec {
+            return Err(format!(
+                "Text overlay \"{}\" has an invalid range: {}s to {}s",
+                self.text, self.start_sec, self.end_sec
+            ));
+        }
+        if self.fade_in_sec < 0.0
+            || self.fade_out_sec < 0.0
+            || self.fade_in_sec + self.fade_out_sec > self.end_sec - self.start_sec
+        {
+            return Err(format!("Fades on text overlay \"{}\" are longer than the overlay", self.text));
+        }
+        if self.font_size == 0 {
+            return Err(format!("Text overlay \"{}\" needs a font size", self.text));
+        }
+        if let Some(font) = &self.font_file {
+            if !std::path::Path::new(font).is_file() {
+                return Err(format!("Font file not found: {}", font));
+            }
+        }
+        if let Some(background) = &self.background {
+            if !(0.0..=1.0).contains(&background.opacity) {
+                return Err("Text background opacity must be between 0 and 1".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Opacity over time: ramps up during the fade-in and down during the fade-out
+    fn alpha_expression(&self) -> String {
+        let (start, end) = (self.start_sec, self.end_sec);
+        let fade_in = if self.fade_in_sec > 0.0 {
+            format!("if(lt(t,{}),(t-{})/{},", start + self.fade_in_sec, start, self.fade_in_sec)
+        } else {
+            "(".to_string()
+        };
+        let fade_out = if self.fade_out_sec > 0.0 {
+            format!("if(gt(t,{}),({}-t)/{},1)", end - self.fade_out_sec, end, self.fade_out_sec)
+        } else {
+            "1".to_string()
+        };
+        format!("{}{})", fade_in, fade_out)
+    }
+
+    /// The `drawtext` filter for this overlay, with every value escaped
+    pub fn drawtext_filter(&self) -> String {
+        let (x, y) = self.position.expressions();
+        let mut options = vec![
+            ("expansion", "none".to_string()),
+            ("text", self.text.clone()),
+            ("fontsize", self.font_size.to_string()),
+            ("fontcolor", self.color.clone()),
+            ("x", x),
+            ("y", y),
+            ("enable", format!("between(t,{},{})", self.start_sec, self.end_sec)),
+            ("alpha", self.alpha_expression()),
+        ];
+        if let Some(font) = &self.font_file {
+            options.push(("fontfile", font.clone()));
+        }
+        if let Some(background) = &self.background {
+            options.push(("box", "1".to_string()));
+            options.push(("boxcolor", format!("{}@{}", background.color, background.opacity)));
+            options.push(("boxborderw", background.padding.to_string()));
+        }
+
+        let options: Vec<String> = options
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, escape_filter_value(&value)))
+            .collect();
+        format!("drawtext={}", options.join(":"))
+    }
+}
+
+/// drawtext filters for all overlays, in drawing order
+pub fn filters(overlays: &[TextOverlay]) -> Vec<String> {
+    overlays.iter().map(TextOverlay::drawtext_filter).collect()
+}
+
+/// Escape a value for use as a filter option inside `-filter_complex`.
+/// ffmpeg unescapes twice: once when splitting the filtergraph into filters
+/// and once when splitting a filter's options, so both levels are applied.
+pub fn escape_filter_value(value: &str) -> String {
+    let mut option_level = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            option_level.push('\\');
+        }
+        option_level.push(c);
+    }
+
+    let mut graph_level = String::with_capacity(option_level.len());
+    for c in option_level.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            graph_level.push('\\');
+        }
+        graph_level.push(c);
+    }
+    graph_level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_values_are_escaped_for_both_parsing_levels() {
+        assert_eq!(escape_filter_value("Hello"), "Hello");
+        assert_eq!(escape_filter_value("a:b"), r"a\\:b");
+        assert_eq!(escape_filter_value("it's"), r"it\\\'s");
+        assert_eq!(escape_filter_value("[x],y;z"), r"\[x\]\,y\;z");
+        assert_eq!(escape_filter_value(r"C:\fonts"), r"C\\:\\\\fonts");
+    }
+
+    #[test]
+    fn text_overlay_builds_drawtext_with_fades() {
+        let overlay = TextOverlay {
+            text: "Step 1: don't panic; 100%".to_string(),
+            font_file: None,
+            font_size: 36,
+            color: "white".to_string(),
+            background: Some(TextBackground {
+                color: "black".to_string(),
+                opacity: 0.5,
+                padding: 10,
+            }),
+            position: TextPosition::LowerThird,
+            start_sec: 1.0,
+            end_sec: 4.0,
+            fade_in_sec: 0.5,
+            fade_out_sec: 1.0,
+        };
+        assert!(overlay.validate().is_ok());
+
+        let filter = overlay.drawtext_filter();
+        assert!(filter.starts_with(r"drawtext=expansion=none:text=Step 1\\: don\\\'t panic\; 100%:"));
+        assert!(filter.contains(r"enable=between(t\,1\,4)"));
+        assert!(filter.contains(r"alpha=if(lt(t\,1.5)\,(t-1)/0.5\,if(gt(t\,3)\,(4-t)/1\,1))"));
+        assert!(filter.contains(":box=1:boxcolor=black@0.5:boxborderw=10"));
+
+        let too_long_fades = TextOverlay { fade_out_sec: 3.0, ..overlay };
+        assert!(too_long_fades.validate().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+
+use super::conform::OutputFormat;
+use super::envelope::VolumeEnvelope;
+use super::ffmpeg::Input;
+
+/// Where an overlay sits in the output frame, as fractions of the frame's
+/// width and height from the top-left corner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverlayRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The `in_sec..out_sec` range of a source, shown from `start_sec` on the
+/// output timeline inside `rect`. Overlay clips are silent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverlayClip {
+    pub source_path: String,
+    pub in_sec: f64,
+    pub out_sec: f64,
+    pub start_sec: f64,
+    pub rect: OverlayRect,
+    /// 0 is invisible, 1 is opaque
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+impl OverlayClip {
+    pub fn end_sec(&self) -> f64 {
+        self.start_sec + self.out_sec - self.in_sec
+    }
+}
+
+/// A video track composited over the main clips, e.g. a webcam over a screen recording
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VideoTrack {
+    /// Stacking order: higher tracks are drawn over lower ones
+    #[serde(default)]
+    pub z_index: i32,
+    pub clips: Vec<OverlayClip>,
+}
+
+impl VideoTrack {
+    /// `timeline_sec` is the length of the main clips, which sets the export length
+    pub fn validate(&self, timeline_sec: f64) -> Result<(), String> {
+        let z = self.z_index;
+        for (i, clip) in self.clips.iter().enumerate() {
+            if clip.in_sec < 0.0 || clip.out_sec <= clip.in_sec {
+                return Err(format!(
+                    "Overlay clip {} on track {} has an invalid range: {}s to {}s",
+                    i, z, clip.in_sec, clip.out_sec
+                ));
+            }
+            if clip.start_sec < 0.0 || clip.start_sec >= timeline_sec {
+                return Err(format!(
+                    "Overlay clip {} on track {} starts at {}s, outside the {:.2}s timeline",
+                    i, z, clip.start_sec, timeline_sec
+                ));
+            }
+            let rect = clip.rect;
+            let inside = |pos: f64, size: f64| pos >= 0.0 && size > 0.0 && pos + size <= 1.0 + 1e-9;
+            if !inside(rect.x, rect.width) || !inside(rect.y, rect.height) {
+                return Err(format!("Overlay clip {} on track {} must sit inside the frame", i, z));
+            }
+            if !(0.0..=1.0).contains(&clip.opacity) {
+                return Err(format!("Overlay clip {} on track {} needs an opacity between 0 and 1", i, z));
+            }
+        }
+
+        let mut spans: Vec<(f64, f64)> = self.clips.iter().map(|c| (c.start_sec, c.end_sec())).collect();
+        spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if spans.windows(2).any(|w| w[1].0 < w[0].1) {
+            return Err(format!("Clips on overlay track {} overlap; put them on separate tracks", z));
+        }
+        Ok(())
+    }
+}
+
+pub fn has_overlays(tracks: &[VideoTrack]) -> bool {
+    tracks.iter().any(|t| !t.clips.is_empty())
+}
+
+/// Overlay clips from the lowest track up, so later ones are drawn on top
+fn stacking_order(tracks: &[VideoTrack]) -> Vec<&OverlayClip> {
+    let mut sorted: Vec<&VideoTrack> = tracks.iter().collect();
+    // Stable, so tracks with the same z keep their request order
+    sorted.sort_by_key(|t| t.z_index);
+    sorted.iter().flat_map(|t| t.clips.iter()).collect()
+}
+
+/// Inputs and filter chains that composite the tracks over `[base]` and end
+/// in `[out]`. `first_input` is the ffmpeg input index of the first overlay clip.
+pub fn overlay_chains(
+    tracks: &[VideoTrack],
+    format: &OutputFormat,
+    first_input: usize,
+    base: &str,
+    out: &str,
+) -> (Vec<Input>, Vec<String>) {
+    let clips = stacking_order(tracks);
+    let frame = format.frame;
+    let mut inputs = Vec::new();
+    let mut chains = Vec::new();
+    let mut below = base.to_string();
+
+    for (i, clip) in clips.iter().enumerate() {
+        let duration = clip.out_sec - clip.in_sec;
+        inputs.push(Input::file(&clip.source_path).seek(clip.in_sec).duration(duration));
+
+        // Box in output pixels; the clip keeps its shape and is centred in it
+        let x = (clip.rect.x * frame.width as f64).round() as u32;
+        let y = (clip.rect.y * frame.height as f64).round() as u32;
+        let width = ((clip.rect.width * frame.width as f64) as u32 & !1).max(2);
+        let height = ((clip.rect.height * frame.height as f64) as u32 & !1).max(2);
+
+        let mut video = format!(
+            "[{}:v]trim=duration={},setpts=PTS-STARTPTS,scale={}:{}:force_original_aspect_ratio=decrease,setsar=1,fps={},format=yuva420p",
+            first_input + i,
+            duration,
+            width,
+            height,
+            format.fps
+        );
+        if clip.opacity < 1.0 {
+            video.push_str(&format!(",colorchannelmixer=aa={}", clip.opacity));
+        }
+        // Shift the clip to where it starts on the timeline
+        video.push_str(&format!(",setpts=PTS+{}/TB[ov{}]", clip.start_sec, i));
+        chains.push(video);
+
+        let above = if i == clips.len() - 1 { out.to_string() } else { format!("ovj{}", i) };
+        chains.push(format!(
+            "[{}][ov{}]overlay=x={}+({}-overlay_w)/2:y={}+({}-overlay_h)/2:eof_action=pass:enable=between(t\\,{}\\,{})[{}]",
+            below,
+            i,
+            x,
+            width,
+            y,
+            height,
+            clip.start_sec,
+            clip.end_sec(),
+            above
+        ));
+        below = above;
+    }
+    (inputs, chains)
+}
+
+/// Gain, mute and solo of a track. With any track soloed, only soloed tracks are heard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackMix {
+    pub gain_db: f64,
+    pub mute: bool,
+    pub solo: bool,
+}
+
+impl TrackMix {
+    /// `what` names the track in the error, e.g. "Audio track 2"
+    pub fn validate(&self, what: &str) -> Result<(), String> {
+        validate_gain(self.gain_db, what)
+    }
+
+    pub fn is_audible(&self, any_solo: bool) -> bool {
+        !self.mute && (self.solo || !any_solo)
+    }
+}
+
+/// The `in_sec..out_sec` range of a source's audio, played from `start_sec`
+/// on the output timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioClip {
+    pub source_path: String,
+    pub in_sec: f64,
+    pub out_sec: f64,
+    pub start_sec: f64,
+    #[serde(default)]
+    pub gain_db: f64,
+    #[serde(default)]
+    pub fade_in_sec: f64,
+    #[serde(default)]
+    pub fade_out_sec: f64,
+    /// Gain keyframed over the clip, on top of `gain_db`
+    #[serde(default)]
+    pub volume: Option<VolumeEnvelope>,
+}
+
+impl AudioClip {
+    pub fn duration(&self) -> f64 {
+        self.out_sec - self.in_sec
+    }
+}
+
+/// An audio track mixed under the timeline, e.g. a music bed or voiceover
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    #[serde(default)]
+    pub name: String,
+    pub clips: Vec<AudioClip>,
+    #[serde(default, flatten)]
+    pub mix: TrackMix,
+}
+
+/// Gains outside this range are almost certainly a mistake
+pub const MIN_GAIN_DB: f64 = -60.0;
+pub const MAX_GAIN_DB: f64 = 24.0;
+
+fn validate_gain(gain_db: f64, what: &str) -> Result<(), String> {
+    if !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&gain_db) {
+        return Err(format!(
+            "{} gain must be between {} and {} dB, got {}",
+            what, MIN_GAIN_DB, MAX_GAIN_DB, gain_db
+        ));
+    }
+    Ok(())
+}
+
+impl AudioTrack {
+    pub fn validate(&self, index: usize) -> Result<(), String> {
+        self.mix.validate(&format!("Audio track {}", index))?;
+        for (i, clip) in self.clips.iter().enumerate() {
+            if clip.in_sec < 0.0 || clip.out_sec <= clip.in_sec || clip.start_sec < 0.0 {
+                return Err(format!(
+                    "Audio clip {} on track {} has an invalid range: {}s to {}s at {}s",
+                    i, index, clip.in_sec, clip.out_sec, clip.start_sec
+                ));
+            }
+            validate_gain(clip.gain_db, &format!("Audio clip {} on track {}", i, index))?;
+            if clip.fade_in_sec < 0.0 || clip.fade_out_sec < 0.0 {
+                return Err(format!("Audio clip {} on track {} has a negative fade", i, index));
+            }
+            if let Some(volume) = &clip.volume {
+                volume.validate(&format!("Audio clip {} on track {}", i, index))?;
+            }
+            if clip.fade_in_sec + clip.fade_out_sec > clip.duration() {
+                return Err(format!(
+                    "Fades on audio clip {} on track {} are longer than the clip",
+                    i, index
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video;
+    use crate::export::{graph, ExportRequest};
+    use crate::export::settings::ExportSettings;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn overlay_tracks_stack_by_z_order() {
+        let overlay = |path: &str, start_sec, x, opacity| OverlayClip {
+            source_path: path.to_string(),
+            in_sec: 0.0,
+            out_sec: 2.0,
+            start_sec,
+            rect: OverlayRect { x, y: 0.75, width: 0.25, height: 0.25 },
+            opacity,
+        };
+        let mut request = ExportRequest {
+            clips: vec![clip("screen.mp4", 0.0, 10.0)],
+            output_path: "out.mp4".to_string(),
+            video_tracks: vec![
+                VideoTrack { z_index: 2, clips: vec![overlay("logo.mp4", 0.0, 0.75, 0.5)] },
+                VideoTrack { z_index: 1, clips: vec![overlay("webcam.mp4", 1.0, 0.0, 1.0)] },
+            ],
+            ..ExportRequest::default()
+        };
+        assert!(request.validate().is_ok());
+
+        let source = stream(1280, 720, 30.0);
+        let mut graph = graph::build(&request.clips, &[source], &ExportSettings::default()).unwrap();
+        graph.append_overlays(&request.video_tracks);
+        assert_eq!(graph.inputs.len(), 3);
+        assert_eq!(graph.video_label, "outov");
+        // The webcam (z 1) goes on first, then the half-transparent logo over it
+        assert!(graph.filter_complex.contains(
+            "[1:v]trim=duration=2,setpts=PTS-STARTPTS,scale=320:180:force_original_aspect_ratio=decrease,setsar=1,fps=30,format=yuva420p,setpts=PTS+1/TB[ov0]"
+        ));
+        assert!(graph.filter_complex.contains(
+            "[outv][ov0]overlay=x=0+(320-overlay_w)/2:y=540+(180-overlay_h)/2:eof_action=pass:enable=between(t\\,1\\,3)[ovj0]"
+        ));
+        assert!(graph.filter_complex.contains("format=yuva420p,colorchannelmixer=aa=0.5,setpts=PTS+0/TB[ov1]"));
+        assert!(graph.filter_complex.contains("[ovj0][ov1]overlay=x=960+"));
+
+        // Two clips can't share a track at the same time
+        request.video_tracks[1].clips.push(overlay("webcam.mp4", 2.5, 0.0, 1.0));
+        assert!(request.validate().is_err());
+        request.video_tracks[1].clips.pop();
+        request.video_tracks[0].clips[0].rect.x = 0.9;
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn overlay_export_composites_webcam_over_screen() {
+        let dir = test_dir("overlay");
+        let screen = make_source(&dir, "screen.mp4", 3.0, "640x360", true);
+        let webcam = make_source(&dir, "webcam.mp4", 2.0, "320x240", false);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![clip(&screen, 0.0, 3.0)],
+            output_path: output.clone(),
+            video_tracks: vec![VideoTrack {
+                z_index: 1,
+                clips: vec![OverlayClip {
+                    source_path: webcam,
+                    in_sec: 0.0,
+                    out_sec: 1.0,
+                    start_sec: 1.0,
+                    rect: OverlayRect { x: 0.7, y: 0.7, width: 0.3, height: 0.3 },
+                    opacity: 0.8,
+                }],
+            }],
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        // The main clips set the size and length; the overlay doesn't add audio or time
+        let info = video::probe_streams(&output).unwrap();
+        assert_eq!((info.width, info.height), (640, 360));
+        assert!(info.has_audio);
+        assert!((probe_duration(&output) - 3.0).abs() < 0.15);
+    }
+}
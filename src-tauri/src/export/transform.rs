@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+
+use super::settings::{ExportSettings, Resolution};
+use super::text::escape_filter_value;
+use super::ClipData;
+use crate::video::StreamInfo;
+
+/// Region of the source frame to keep, in source pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How a clip whose shape differs from the output frame is fitted into it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    /// Scale to fit inside the frame and pad the rest
+    #[default]
+    Fit,
+    /// Scale to cover the frame and crop what sticks out
+    Fill,
+    /// Scale to the frame size, ignoring the aspect ratio
+    Stretch,
+}
+
+/// Geometry changes applied to a clip before it's fitted into the output frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipTransform {
+    #[serde(default)]
+    pub crop: Option<CropRect>,
+    /// Clockwise; multiples of 90 are lossless, anything else fills the corners
+    #[serde(default)]
+    pub rotation_deg: f64,
+    #[serde(default)]
+    pub flip_horizontal: bool,
+    #[serde(default)]
+    pub flip_vertical: bool,
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
+    /// Colour of letterbox bars and of the corners left by arbitrary rotations
+    #[serde(default = "default_pad_color")]
+    pub pad_color: String,
+}
+
+fn default_pad_color() -> String {
+    "black".to_string()
+}
+
+impl Default for ClipTransform {
+    fn default() -> Self {
+        ClipTransform {
+            crop: None,
+            rotation_deg: 0.0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            scale_mode: ScaleMode::Fit,
+            pad_color: default_pad_color(),
+        }
+    }
+}
+
+impl ClipTransform {
+    pub fn validate(&self, index: usize) -> Result<(), String> {
+        if let Some(crop) = &self.crop {
+            if crop.width == 0 || crop.height == 0 || crop.width % 2 != 0 || crop.height % 2 != 0 {
+                return Err(format!(
+                    "Crop on clip {} is {}x{}: width and height must be even and non-zero",
+                    index, crop.width, crop.height
+                ));
+            }
+        }
+        if !self.rotation_deg.is_finite() {
+            return Err(format!("Clip {} has an invalid rotation", index));
+        }
+        if self.pad_color.trim().is_empty() {
+            return Err(format!("Clip {} needs a pad colour", index));
+        }
+        Ok(())
+    }
+
+    /// Check the crop fits inside a `width`x`height` source
+    pub fn validate_crop_within(&self, index: usize, width: u32, height: u32) -> Result<(), String> {
+        match &self.crop {
+            Some(crop)
+                if crop.x.saturating_add(crop.width) > width
+                    || crop.y.saturating_add(crop.height) > height =>
+            {
+                Err(format!(
+                    "Crop on clip {} ({}x{} at {},{}) goes outside the {}x{} source",
+                    index, crop.width, crop.height, crop.x, crop.y, width, height
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rotation in 0..360 degrees
+    fn rotation(&self) -> f64 {
+        self.rotation_deg.rem_euclid(360.0)
+    }
+
+    /// Frame size after cropping and rotating a `width`x`height` source
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (w, h) = match self.crop {
+            Some(crop) => (crop.width, crop.height),
+            None => (width, height),
+        };
+        let rotation = self.rotation();
+        if rotation == 0.0 || rotation == 180.0 {
+            (w, h)
+        } else if rotation == 90.0 || rotation == 270.0 {
+            (h, w)
+        } else {
+            // Bounding box of the rotated frame, like ffmpeg's rotw()/roth()
+            let (sin, cos) = rotation.to_radians().sin_cos();
+            let (w, h) = (w as f64, h as f64);
+            let rotated_w = w * cos.abs() + h * sin.abs();
+            let rotated_h = w * sin.abs() + h * cos.abs();
+            (rotated_w as u32 & !1, rotated_h as u32 & !1)
+        }
+    }
+
+    /// crop, flip and rotate filters, in that order
+    fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if let Some(crop) = &self.crop {
+            filters.push(format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y));
+        }
+        if self.flip_horizontal {
+            filters.push("hflip".to_string());
+        }
+        if self.flip_vertical {
+            filters.push("vflip".to_string());
+        }
+
+        let rotation = self.rotation();
+        if rotation == 90.0 {
+            filters.push("transpose=clock".to_string());
+        } else if rotation == 180.0 {
+            filters.extend(["hflip".to_string(), "vflip".to_string()]);
+        } else if rotation == 270.0 {
+            filters.push("transpose=cclock".to_string());
+        } else if rotation != 0.0 {
+            let radians = rotation.to_radians();
+            filters.push(format!(
+                "rotate={a}:ow=trunc(rotw({a})/2)*2:oh=trunc(roth({a})/2)*2:fillcolor={color}",
+                a = radians,
+                color = escape_filter_value(&self.pad_color)
+            ));
+        }
+        filters
+    }
+}
+
+/// Size every clip is brought to: the export resolution, or else the first
+/// clip's size after its transform (rounded down to even numbers)
+pub fn output_frame(clips: &[ClipData], sources: &[StreamInfo], settings: &ExportSettings) -> Resolution {
+    settings.resolution.unwrap_or_else(|| {
+        let (width, height) = transformed_size(&clips[0], &sources[0]);
+        Resolution {
+            width: width & !1,
+            height: height & !1,
+        }
+    })
+}
+
+/// Size of a clip's frames after its transform
+pub fn transformed_size(clip: &ClipData, source: &StreamInfo) -> (u32, u32) {
+    match &clip.transform {
+        Some(transform) => transform.output_size(source.width, source.height),
+        None => (source.width, source.height),
+    }
+}
+
+/// Filters that apply the clip's transform and fit the result into `frame`,
+/// ending with square pixels. Only `setsar=1` when the clip already matches
+/// the frame untouched.
+pub fn clip_video_filters(clip: &ClipData, source: &StreamInfo, frame: Resolution) -> Vec<String> {
+    let default = ClipTransform::default();
+    let transform = clip.transform.as_ref().unwrap_or(&default);
+    let mut filters = transform.filters();
+
+    let (width, height) = transformed_size(clip, source);
+    if (width, height) == (frame.width, frame.height) {
+        // Same size can still mean non-square pixels, which concat and xfade reject
+        filters.push("setsar=1".to_string());
+        return filters;
+    }
+
+    let (w, h) = (frame.width, frame.height);
+    match transform.scale_mode {
+        ScaleMode::Fit => filters.extend([
+            // Even dimensions, or yuv420p can't hold the scaled frame
+            format!("scale={}:{}:force_original_aspect_ratio=decrease:force_divisible_by=2", w, h),
+            format!("pad={}:{}:(ow-iw)/2:(oh-ih)/2:color={}", w, h, escape_filter_value(&transform.pad_color)),
+        ]),
+        ScaleMode::Fill => filters.extend([
+            format!("scale={}:{}:force_original_aspect_ratio=increase", w, h),
+            format!("crop={}:{}", w, h),
+        ]),
+        ScaleMode::Stretch => filters.push(format!("scale={}:{}", w, h)),
+    }
+    filters.push("setsar=1".to_string());
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{settings, ExportRequest};
+    use crate::export::test_support::*;
+
+    #[test]
+    fn clip_transforms_crop_rotate_and_fill_the_frame() {
+        let phone = ClipData {
+            transform: Some(ClipTransform {
+                crop: Some(CropRect { x: 0, y: 100, width: 640, height: 1080 }),
+                rotation_deg: -90.0,
+                flip_horizontal: true,
+                scale_mode: ScaleMode::Fill,
+                ..ClipTransform::default()
+            }),
+            ..clip("phone.mp4", 0.0, 1.0)
+        };
+        let source = stream(1080, 1920, 30.0);
+        assert_eq!(transformed_size(&phone, &source), (1080, 640));
+
+        let frame = settings::Resolution { width: 1280, height: 720 };
+        assert_eq!(
+            clip_video_filters(&phone, &source, frame),
+            vec![
+                "crop=640:1080:0:100",
+                "hflip",
+                "transpose=cclock",
+                "scale=1280:720:force_original_aspect_ratio=increase",
+                "crop=1280:720",
+                "setsar=1",
+            ]
+        );
+
+        let tilted = ClipTransform { rotation_deg: 30.0, ..ClipTransform::default() };
+        assert_eq!(tilted.output_size(640, 480), (794, 734));
+        assert!(ClipTransform {
+            crop: Some(CropRect { x: 0, y: 0, width: 301, height: 200 }),
+            ..ClipTransform::default()
+        }
+        .validate(0)
+        .is_err());
+
+        let crop = ClipTransform {
+            crop: Some(CropRect { x: 640, y: 0, width: 640, height: 720 }),
+            ..ClipTransform::default()
+        };
+        assert!(crop.validate_crop_within(0, 1280, 720).is_ok());
+        assert!(crop.validate_crop_within(0, 1278, 720).is_err());
+        assert!(crop.validate_crop_within(0, 1280, 718).is_err());
+
+        // Already the frame's size: only the pixels are made square
+        let square = clip("square.mp4", 0.0, 1.0);
+        assert_eq!(clip_video_filters(&square, &stream(1280, 720, 30.0), frame), vec!["setsar=1"]);
+        let cropped = ClipData {
+            transform: Some(ClipTransform {
+                crop: Some(CropRect { x: 0, y: 0, width: 1280, height: 720 }),
+                ..ClipTransform::default()
+            }),
+            ..square.clone()
+        };
+        assert_eq!(
+            clip_video_filters(&cropped, &stream(1920, 1080, 30.0), frame),
+            vec!["crop=1280:720:0:0", "setsar=1"]
+        );
+
+        // Fitted frames keep even dimensions
+        assert_eq!(
+            clip_video_filters(&square, &stream(500, 500, 30.0), frame)[0],
+            "scale=1280:720:force_original_aspect_ratio=decrease:force_divisible_by=2"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn mixed_resolution_clips_are_transformed_into_the_first_frame() {
+        let dir = test_dir("transform");
+        let landscape = make_source(&dir, "landscape.mp4", 2.0, "320x240", true);
+        let portrait = make_source(&dir, "portrait.mp4", 2.0, "240x320", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![
+                clip(&landscape, 0.0, 2.0),
+                ClipData {
+                    transform: Some(ClipTransform { rotation_deg: 90.0, flip_vertical: true, ..ClipTransform::default() }),
+                    ..clip(&portrait, 0.0, 2.0)
+                },
+                clip(&portrait, 0.0, 1.0),
+            ],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert_eq!(probe_entries(&output, Some("v"), "stream=width,height"), "320,240");
+        assert!((probe_duration(&output) - 5.0).abs() < 0.15);
+
+        // Crops are checked against the probed source before anything renders
+        let mut cropped = request.clone();
+        cropped.clips[0].transform = Some(ClipTransform {
+            crop: Some(CropRect { x: 10, y: 0, width: 320, height: 240 }),
+            ..ClipTransform::default()
+        });
+        assert!(cropped.validate().is_ok());
+        assert!(export(&cropped).unwrap_err().contains("outside the 320x240 source"));
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// How one clip hands over to the next
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    Crossfade,
+    DipToBlack,
+    DipToWhite,
+    Wipe,
+    Slide,
+}
+
+/// Direction the incoming clip moves in, for wipes and slides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionDirection {
+    #[default]
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl TransitionDirection {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransitionDirection::Left => "left",
+            TransitionDirection::Right => "right",
+            TransitionDirection::Up => "up",
+            TransitionDirection::Down => "down",
+        }
+    }
+}
+
+/// Transition from a clip into the one after it. The two clips overlap by
+/// `duration_sec`, so the timeline gets that much shorter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration_sec: f64,
+    #[serde(default)]
+    pub direction: TransitionDirection,
+}
+
+impl Transition {
+    /// Name of the matching ffmpeg `xfade` transition
+    pub fn xfade_name(&self) -> String {
+        match self.kind {
+            TransitionKind::Crossfade => "fade".to_string(),
+            TransitionKind::DipToBlack => "fadeblack".to_string(),
+            TransitionKind::DipToWhite => "fadewhite".to_string(),
+            TransitionKind::Wipe => format!("wipe{}", self.direction.as_str()),
+            TransitionKind::Slide => format!("slide{}", self.direction.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportRequest;
+    use crate::export::test_support::*;
+
+    #[test]
+    fn transitions_are_validated_and_shorten_the_timeline() {
+        use TransitionKind::Crossfade;
+        let request = |clips| ExportRequest {
+            clips,
+            output_path: "out.mp4".to_string(),
+            ..ExportRequest::default()
+        };
+
+        let ok = request(vec![with_transition(clip("a.mp4", 0.0, 2.0), Crossfade, 0.5), clip("b.mp4", 0.0, 3.0)]);
+        assert!(ok.validate().is_ok());
+        assert!((ok.total_duration() - 4.5).abs() < 1e-9);
+
+        // Longer than the clip it fades into
+        let too_long = request(vec![with_transition(clip("a.mp4", 0.0, 3.0), Crossfade, 1.5), clip("b.mp4", 0.0, 1.0)]);
+        assert!(too_long.validate().is_err());
+
+        // Nothing to transition into
+        let last = request(vec![clip("a.mp4", 0.0, 2.0), with_transition(clip("b.mp4", 0.0, 2.0), Crossfade, 0.5)]);
+        assert!(last.validate().is_err());
+
+        // Middle clip can't hold both of its transitions
+        let squeezed = request(vec![
+            with_transition(clip("a.mp4", 0.0, 2.0), Crossfade, 0.8),
+            with_transition(clip("b.mp4", 0.0, 1.0), Crossfade, 0.8),
+            clip("c.mp4", 0.0, 2.0),
+        ]);
+        assert!(squeezed.validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn crossfaded_export_overlaps_clips() {
+        let dir = test_dir("transitions");
+        let source = make_source(&dir, "src.mp4", 4.0, "320x240", true);
+        let output = dir.join("out.mp4").to_string_lossy().to_string();
+
+        let request = ExportRequest {
+            clips: vec![
+                with_transition(clip(&source, 0.0, 2.0), TransitionKind::Crossfade, 0.5),
+                clip(&source, 2.0, 4.0),
+            ],
+            output_path: output.clone(),
+            ..ExportRequest::default()
+        };
+        export(&request).unwrap();
+
+        assert!((probe_duration(&output) - 3.5).abs() < 0.15);
+    }
+}
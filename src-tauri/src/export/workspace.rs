@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_WORKSPACE: AtomicU64 = AtomicU64::new(1);
+
+/// Scratch directory for one export's intermediate files. The directory and
+/// everything in it is removed when the workspace is dropped, whether the
+/// export succeeded, failed or was cancelled.
+#[derive(Debug)]
+pub struct Workspace {
+    dir: PathBuf,
+}
+
+impl Workspace {
+    pub fn create() -> Result<Self, String> {
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let dir = std::env::temp_dir().join(format!(
+            "clipforge_export_{}_{}",
+            millis,
+            NEXT_WORKSPACE.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        Ok(Workspace { dir })
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Write a list for ffmpeg's concat demuxer and return its path
+    pub fn write_concat_list(&self, name: &str, files: &[PathBuf]) -> Result<PathBuf, String> {
+        let content: String = files
+            .iter()
+            .map(|p| format!("file '{}'", p.to_string_lossy().replace('\'', r"'\''")))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let list = self.path(name);
+        std::fs::write(&list, content).map_err(|e| format!("Failed to write concat list: {}", e))?;
+        Ok(list)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
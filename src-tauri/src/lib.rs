@@ -1,123 +1,124 @@
-use serde::{Deserialize, Serialize};
-use std::process::Command;
-use std::fs;
-use std::path::PathBuf;
-
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+use export::cache::{CacheInfo, SegmentCache};
+use export::cleanup::{self, CleanupPreview};
+use export::jobs::{self, ExportJob, ExportJobStatus};
+use export::progress::RenderProgress;
+use export::settings::{self, ExportPreset};
+use export::subtitles::{self, BurnInSubtitles};
+use export::{ClipData, ExportContext, ExportRequest, ExportResult};
+
+mod export;
 mod recording;
 mod video;
 mod transcription;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ClipData {
-    source_path: String,
-    in_sec: f64,
-    out_sec: f64,
+fn emit_progress(app: &AppHandle, job: &ExportJob, progress: RenderProgress) {
+    let event = job.set_progress(progress);
+    if let Err(e) = app.emit("export-progress", event) {
+        println!("⚠️ Failed to emit export progress: {}", e);
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct ExportProgress {
-    status: String,
-    message: String,
+/// Run `work` for `job` on a background thread, then record and emit its
+/// outcome. A panic fails the job rather than leaving it running forever.
+fn spawn_job<F>(app: AppHandle, job: Arc<ExportJob>, work: F)
+where
+    F: FnOnce(&ExportContext) -> Result<ExportResult, String> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let ctx = ExportContext {
+            cancel: &job.cancel,
+            on_progress: &|p| emit_progress(&app, &job, p),
+        };
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| work(&ctx)))
+            .unwrap_or_else(|_| Err("The job stopped unexpectedly".to_string()));
+        match &result {
+            Ok(_) => println!("✅ Job {} completed successfully!", job.id),
+            Err(e) => println!("❌ Job {} ended: {}", job.id, e),
+        }
+        let status = job.finish(result);
+
+        if let Err(e) = app.emit("export-progress", status.final_progress()) {
+            println!("⚠️ Failed to emit export progress: {}", e);
+        }
+    });
 }
 
+/// Start an export in the background and return its job id. Everything
+/// except `clips` and `output_path` is optional in the request.
 #[tauri::command]
-fn export_video(clips: Vec<ClipData>, output_path: String) -> Result<String, String> {
-    println!("🎬 Starting export with {} clips", clips.len());
-    println!("📁 Output path: {}", output_path);
+fn export_video(app: AppHandle, request: ExportRequest) -> Result<String, String> {
+    println!("🎬 Starting export with {} clips", request.clips.len());
+    println!("📁 Output path: {}", request.output_path);
 
-    if clips.is_empty() {
-        return Err("No clips to export".to_string());
-    }
+    request.validate()?;
 
-    // Create temp directory for intermediate files
-    let temp_dir = std::env::temp_dir().join("clipforge_export");
-    fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let job = jobs::create_job(&request.output_path);
+    let job_id = job.id.clone();
+    spawn_job(app, job, move |ctx| export::run(&request, ctx));
 
-    // Step 1: Trim each clip to a temporary file
-    let mut trimmed_files: Vec<PathBuf> = Vec::new();
-    
-    for (i, clip) in clips.iter().enumerate() {
-        let temp_output = temp_dir.join(format!("clip_{}.mp4", i));
-        println!("✂️ Trimming clip {}: {}s to {}s", i, clip.in_sec, clip.out_sec);
-
-        let duration = clip.out_sec - clip.in_sec;
-
-        let output = Command::new("ffmpeg")
-            .args(&[
-                "-y", // Overwrite output files
-                "-ss", &clip.in_sec.to_string(),
-                "-i", &clip.source_path,
-                "-t", &duration.to_string(),
-                "-c:v", "libx264",
-                "-preset", "fast",
-                "-crf", "22",
-                "-c:a", "aac",
-                "-movflags", "+faststart",
-                temp_output.to_str().unwrap(),
-            ])
-            .output()
-            .map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("FFmpeg trim failed for clip {}: {}", i, stderr));
-        }
+    Ok(job_id)
+}
 
-        trimmed_files.push(temp_output);
-    }
+#[tauri::command]
+fn get_export_status(job_id: String) -> Result<ExportJobStatus, String> {
+    jobs::get_status(&job_id)
+}
 
-    // Step 2: Create concat file list
-    let concat_list = temp_dir.join("concat_list.txt");
-    let concat_content: String = trimmed_files
-        .iter()
-        .map(|p| format!("file '{}'", p.to_str().unwrap()))
-        .collect::<Vec<String>>()
-        .join("\n");
-
-    fs::write(&concat_list, concat_content.clone())
-        .map_err(|e| format!("Failed to write concat list: {}", e))?;
-
-    println!("📋 Concat list created with {} files", trimmed_files.len());
-    println!("📄 Concat list content:\n{}", concat_content);
-
-    // Step 3: Concatenate all trimmed clips
-    println!("🔗 Concatenating clips with re-encoding for compatibility...");
-    let output = Command::new("ffmpeg")
-        .args(&[
-            "-y",
-            "-f", "concat",
-            "-safe", "0",
-            "-i", concat_list.to_str().unwrap(),
-            "-c:v", "libx264",      // Re-encode video to ensure compatibility
-            "-preset", "fast",
-            "-crf", "22",
-            "-c:a", "aac",          // Re-encode audio
-            "-movflags", "+faststart",
-            &output_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to execute ffmpeg concat: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        println!("❌ FFmpeg stderr:\n{}", stderr);
-        println!("📤 FFmpeg stdout:\n{}", stdout);
-        return Err(format!("FFmpeg concat failed: {}", stderr));
-    }
-    
-    println!("✅ Concat completed successfully");
+#[tauri::command]
+fn list_export_presets() -> Vec<ExportPreset> {
+    settings::presets()
+}
 
-    // Step 4: Clean up temp files
-    println!("🧹 Cleaning up temp files...");
-    for file in trimmed_files {
-        let _ = fs::remove_file(file);
-    }
-    let _ = fs::remove_file(concat_list);
-    let _ = fs::remove_dir(temp_dir);
+#[tauri::command]
+fn cancel_export(job_id: String) -> Result<(), String> {
+    jobs::cancel(&job_id)
+}
+
+/// Write the timeline's transcript captions as an .srt or .ass file
+#[tauri::command]
+fn export_subtitle_file(
+    clips: Vec<ClipData>,
+    subtitles: BurnInSubtitles,
+    output_path: String,
+) -> Result<String, String> {
+    subtitles.style.validate()?;
+    let cues = subtitles::remap_segments(&clips, &export::clip_start_times(&clips), &subtitles.transcripts);
+    let cues = subtitles::wrap_cues(&cues, subtitles.style.max_chars_per_line);
+
+    let content = if output_path.to_lowercase().ends_with(".ass") {
+        // Without a frame size, lay the script out for 1080p
+        subtitles::to_ass(&cues, &subtitles.style, 1920, 1080)?
+    } else {
+        subtitles::to_srt(&cues)
+    };
+    std::fs::write(&output_path, content).map_err(|e| format!("Failed to write subtitles: {}", e))?;
+
+    println!("💬 Wrote {} captions to {}", cues.len(), output_path);
+    Ok(output_path)
+}
 
-    println!("✅ Export completed successfully!");
-    Ok(format!("Export completed: {}", output_path))
+/// Render a short sample of a clip with its audio cleanup applied, for A/B
+/// listening against the source before a full export
+#[tauri::command(async)]
+fn preview_audio_cleanup(clip: ClipData, start_sec: Option<f64>) -> Result<CleanupPreview, String> {
+    cleanup::render_preview(&clip, start_sec)
+}
+
+#[tauri::command]
+fn get_export_cache_info() -> Result<CacheInfo, String> {
+    Ok(SegmentCache::open()?.info())
+}
+
+/// Delete every cached segment and report how much was freed
+#[tauri::command]
+fn purge_export_cache() -> Result<CacheInfo, String> {
+    let removed = SegmentCache::open()?.purge()?;
+    println!("🧹 Purged {} cached segments ({} bytes)", removed.entries, removed.total_bytes);
+    Ok(removed)
 }
 
 #[tauri::command]
@@ -157,6 +158,24 @@ fn import_video(path: String) -> Result<video::VideoMetadata, String> {
     video::import_video(path).map_err(|e| e.message)
 }
 
+/// Convert a variable frame rate recording to a constant frame rate proxy in
+/// the background and return its job id. It reports through the same events
+/// and commands as exports; the proxy is the job's `output_path`.
+#[tauri::command(async)]
+fn conform_to_cfr(app: AppHandle, path: String) -> Result<String, String> {
+    let proxy = video::cfr_proxy(&path).map_err(|e| e.message)?;
+
+    let job = jobs::create_job(&proxy.path);
+    let job_id = job.id.clone();
+    spawn_job(app, job, move |ctx| {
+        video::conform_to_cfr(&path, &proxy, ctx)
+            .map(|_| ExportResult::default())
+            .map_err(|e| e.message)
+    });
+
+    Ok(job_id)
+}
+
 #[tauri::command]
 fn generate_thumbnail(path: String) -> Result<String, String> {
     video::generate_thumbnail(path).map_err(|e| e.message)
@@ -186,7 +205,7 @@ fn save_blob_to_file(data: Vec<u8>, file_path: String) -> Result<String, String>
 }
 
 #[tauri::command]
-async fn transcribe_video(video_path: String) -> Result<String, String> {
+async fn transcribe_video(video_path: String) -> Result<transcription::Transcript, String> {
     println!("🎤 Starting transcription for: {}", video_path);
     transcription::transcribe_video(&video_path).await
 }
@@ -200,12 +219,20 @@ pub fn run() {
     .plugin(tauri_plugin_dialog::init())
     .invoke_handler(tauri::generate_handler![
       export_video,
+      get_export_status,
+      cancel_export,
+      export_subtitle_file,
+      preview_audio_cleanup,
+      get_export_cache_info,
+      purge_export_cache,
+      list_export_presets,
       get_screen_sources,
       start_recording,
       stop_recording,
       is_recording,
       get_recording_duration,
       import_video,
+      conform_to_cfr,
       generate_thumbnail,
       save_blob_to_file,
       transcribe_video
@@ -6,6 +6,31 @@ use std::process::Command;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TranscriptionResponse {
     pub text: String,
+    #[serde(default)]
+    pub segments: Vec<WhisperSegment>,
+}
+
+/// Segment of Whisper's `verbose_json` response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WhisperSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// A timed piece of the transcript, in source file time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start_sec: f64,
+    pub end_sec: f64,
+    pub text: String,
+}
+
+/// Full transcript plus its timed segments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub text: String,
+    pub segments: Vec<TranscriptSegment>,
 }
 
 #[derive(Debug, Serialize)]
@@ -72,7 +97,7 @@ pub fn extract_audio(video_path: &str) -> Result<String, String> {
 }
 
 /// Call OpenAI Whisper API to transcribe audio
-pub async fn transcribe_audio(audio_path: &str, api_key: &str) -> Result<String, String> {
+pub async fn transcribe_audio(audio_path: &str, api_key: &str) -> Result<Transcript, String> {
     println!("🤖 Transcribing audio with OpenAI Whisper...");
     
     // Read audio file
@@ -87,7 +112,9 @@ pub async fn transcribe_audio(audio_path: &str, api_key: &str) -> Result<String,
     
     let form = reqwest::multipart::Form::new()
         .part("file", part)
-        .text("model", "whisper-1");
+        .text("model", "whisper-1")
+        .text("response_format", "verbose_json")
+        .text("timestamp_granularities[]", "segment");
     
     // Make API request
     let client = reqwest::Client::new();
@@ -110,16 +137,31 @@ pub async fn transcribe_audio(audio_path: &str, api_key: &str) -> Result<String,
         .await
         .map_err(|e| format!("Failed to parse response: {}", e))?;
     
-    println!("✅ Transcription completed: {} characters", transcription.text.len());
+    println!(
+        "✅ Transcription completed: {} characters in {} segments",
+        transcription.text.len(),
+        transcription.segments.len()
+    );
     
     // Clean up temp audio file
     let _ = fs::remove_file(audio_path);
     
-    Ok(transcription.text)
+    Ok(Transcript {
+        text: transcription.text,
+        segments: transcription
+            .segments
+            .into_iter()
+            .map(|s| TranscriptSegment {
+                start_sec: s.start,
+                end_sec: s.end,
+                text: s.text.trim().to_string(),
+            })
+            .collect(),
+    })
 }
 
-/// Full transcription pipeline: extract audio -> transcribe -> return text
-pub async fn transcribe_video(video_path: &str) -> Result<String, String> {
+/// Full transcription pipeline: extract audio -> transcribe -> return timed transcript
+pub async fn transcribe_video(video_path: &str) -> Result<Transcript, String> {
     // Load API key from environment
     let api_key = std::env::var("OPENAI_API_KEY")
         .map_err(|_| "OPENAI_API_KEY not set in environment. Please add it to .env file.".to_string())?;
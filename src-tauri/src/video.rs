@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::export::ffmpeg::{FfmpegCommand, Input};
+use crate::export::progress::ProgressTracker;
+use crate::export::ExportContext;
 
 /// Video metadata extracted from file
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,6 +16,9 @@ pub struct VideoMetadata {
     pub fps: f64,
     pub codec: String,
     pub size: u64,
+    /// Frames aren't evenly spaced, as in most screen recordings
+    #[serde(default)]
+    pub variable_frame_rate: bool,
 }
 
 /// Error type for video operations
@@ -60,6 +68,7 @@ pub fn import_video(path: String) -> Result<VideoMetadata, VideoError> {
             fps: 30.0,
             codec: "h264".to_string(),
             size: 0,
+            variable_frame_rate: false,
         });
     }
 
@@ -88,7 +97,7 @@ pub fn import_video(path: String) -> Result<VideoMetadata, VideoError> {
             "-select_streams",
             "v:0",
             "-show_entries",
-            "stream=width,height,r_frame_rate,codec_name:format=duration",
+            "stream=width,height,r_frame_rate,avg_frame_rate,codec_name:format=duration",
             "-of",
             "json",
             &final_path,
@@ -128,10 +137,11 @@ pub fn import_video(path: String) -> Result<VideoMetadata, VideoError> {
 
     let codec = stream["codec_name"].as_str().unwrap_or("h264").to_string();
 
-    // Parse frame rate (e.g., "30/1" or "30000/1001")
-    let frame_rate_str = stream["r_frame_rate"].as_str().unwrap_or("30/1");
-
-    let fps = parse_frame_rate(frame_rate_str);
+    // Frame rate (e.g., "30/1" or "30000/1001"), averaged for variable frame rate sources
+    let (fps, variable_frame_rate) = detect_frame_rate(&final_path, stream);
+    if variable_frame_rate {
+        println!("⚠️ Variable frame rate source, averaging {:.2}fps", fps);
+    }
 
     // Get duration - try format first, then stream, then probe with ffprobe -count_packets
     let duration = parsed["format"]["duration"]
@@ -163,6 +173,7 @@ pub fn import_video(path: String) -> Result<VideoMetadata, VideoError> {
         fps,
         codec,
         size,
+        variable_frame_rate,
     })
 }
 
@@ -228,6 +239,123 @@ pub fn convert_mov_to_mp4(input_path: &str) -> Result<String, VideoError> {
     Ok(output_path.to_string_lossy().to_string())
 }
 
+/// Where `conform_to_cfr` writes a source's constant frame rate proxy
+#[derive(Debug, Clone)]
+pub struct CfrProxy {
+    pub path: String,
+    /// Rate the proxy is conformed to
+    pub fps: f64,
+    has_audio: bool,
+    duration_sec: f64,
+}
+
+/// Work out the proxy for a variable frame rate source. The cache key has
+/// the file's size and modification time, so a new recording saved over an
+/// old one isn't served the old proxy.
+pub fn cfr_proxy(input_path: &str) -> Result<CfrProxy, VideoError> {
+    let source = probe_streams(input_path)?;
+    if !source.has_video {
+        return Err(VideoError {
+            message: "No video stream found".to_string(),
+        });
+    }
+    // Screen recorders sometimes average a few fps off a common rate
+    let fps = source.frame_rate.round().clamp(1.0, 60.0);
+
+    let file = std::fs::metadata(input_path)?;
+    let modified = file
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let key = md5::compute(format!("{}|{}|{}", input_path, file.len(), modified));
+
+    let temp_dir = std::env::temp_dir().join("clipforge_cfr");
+    std::fs::create_dir_all(&temp_dir).map_err(|e| VideoError {
+        message: format!("Failed to create temp directory: {}", e),
+    })?;
+
+    Ok(CfrProxy {
+        path: temp_dir
+            .join(format!("{:x}_{}fps.mp4", key, fps))
+            .to_string_lossy()
+            .to_string(),
+        fps,
+        has_audio: source.has_audio,
+        duration_sec: probe_format_duration(input_path)
+            .or_else(|| probe_duration_with_packets(input_path).ok())
+            .unwrap_or(0.0),
+    })
+}
+
+/// Numbers the temp files of concurrent `conform_to_cfr` encodes
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Re-encode a variable frame rate source to its constant frame rate proxy, so
+/// trims land on the frames shown in the preview. Audio is stretched to its
+/// timestamps (`aresample=async`) so long recordings stay in sync.
+pub fn conform_to_cfr(
+    input_path: &str,
+    proxy: &CfrProxy,
+    ctx: &ExportContext,
+) -> Result<(), VideoError> {
+    println!("🔄 Conforming to constant frame rate: {}", input_path);
+
+    if std::path::Path::new(&proxy.path).exists() {
+        println!("✅ CFR proxy already exists: {}", proxy.path);
+        return Ok(());
+    }
+
+    // Write to a temp name of our own, so an interrupted encode isn't mistaken for
+    // a finished proxy and two conforms of the same source don't share a file
+    let partial = format!(
+        "{}.{}_{}.partial.mp4",
+        proxy.path.trim_end_matches(".mp4"),
+        std::process::id(),
+        NEXT_PARTIAL.fetch_add(1, Ordering::SeqCst)
+    );
+    let mut cmd = FfmpegCommand::new(&partial);
+    cmd.input(Input::file(input_path));
+    cmd.video_filters(&[format!("fps={}", proxy.fps)]);
+    if proxy.has_audio {
+        cmd.audio_filters(&["aresample=async=1000".to_string()]);
+        cmd.output_args(["-c:a", "aac", "-ar", "48000"]);
+    }
+    cmd.output_args([
+        "-c:v",
+        "libx264",
+        "-crf",
+        "18",
+        "-preset",
+        "veryfast",
+        "-pix_fmt",
+        "yuv420p",
+        "-movflags",
+        "+faststart",
+    ]);
+
+    let mut tracker = ProgressTracker::new(vec![(
+        "Converting to constant frame rate".to_string(),
+        proxy.duration_sec,
+    )]);
+    let result = cmd.run(ctx.cancel, |p| {
+        tracker.update(0, p.out_time_sec);
+        (ctx.on_progress)(tracker.snapshot(0, Some(p)));
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        println!("❌ ffmpeg CFR conform error: {}", e);
+        return Err(VideoError {
+            message: format!("FFmpeg CFR conform failed: {}", e),
+        });
+    }
+    std::fs::rename(&partial, &proxy.path)?;
+
+    println!("✅ CFR proxy at {}fps: {}", proxy.fps, proxy.path);
+    Ok(())
+}
+
 /// Generate a thumbnail for a video file
 pub fn generate_thumbnail(path: String) -> Result<String, VideoError> {
     println!("🖼️ Generating thumbnail for: {}", path);
@@ -298,6 +426,153 @@ pub fn generate_thumbnail(path: String) -> Result<String, VideoError> {
     Ok(thumbnail_path.to_string_lossy().to_string())
 }
 
+/// Stream layout of a source file, as needed to plan an export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamInfo {
+    pub has_video: bool,
+    pub has_audio: bool,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub variable_frame_rate: bool,
+}
+
+/// Probe which streams a file contains and the size and rate of its first video stream
+pub fn probe_streams(path: &str) -> Result<StreamInfo, VideoError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=codec_type,width,height,r_frame_rate,avg_frame_rate",
+            "-of",
+            "json",
+            path,
+        ])
+        .output()
+        .map_err(|e| VideoError {
+            message: format!("FFprobe execution error: {}", e),
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(VideoError {
+            message: format!("FFprobe failed for {}: {}", path, error_msg),
+        });
+    }
+
+    let parsed: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|e| VideoError {
+            message: format!("JSON parse error: {}", e),
+        })?;
+    let streams = parsed["streams"].as_array().cloned().unwrap_or_default();
+
+    let video = streams.iter().find(|s| s["codec_type"] == "video");
+    let has_audio = streams.iter().any(|s| s["codec_type"] == "audio");
+    let (frame_rate, variable_frame_rate) = match video {
+        Some(stream) => detect_frame_rate(path, stream),
+        None => (30.0, false),
+    };
+
+    Ok(StreamInfo {
+        has_video: video.is_some(),
+        has_audio,
+        width: video.and_then(|v| v["width"].as_u64()).unwrap_or(0) as u32,
+        height: video.and_then(|v| v["height"].as_u64()).unwrap_or(0) as u32,
+        frame_rate,
+        variable_frame_rate,
+    })
+}
+
+/// How far apart `r_frame_rate` and `avg_frame_rate` may be, relative to the
+/// average, before a stream counts as variable frame rate
+const MAX_RATE_SPREAD: f64 = 0.01;
+/// How far a frame interval may stray from the median before it counts as irregular
+const MAX_INTERVAL_JITTER: f64 = 0.25;
+/// Share of irregular intervals that makes a stream variable frame rate
+const MAX_IRREGULAR_SHARE: f64 = 0.05;
+/// How much of the file is read to sample frame timestamps
+const TIMESTAMP_SAMPLE_SEC: u32 = 10;
+
+/// Frame rate of a video stream from ffprobe, and whether it's variable.
+/// Variable streams report their average rate, since `r_frame_rate` is
+/// just the time base for them (1000fps for MediaRecorder WebMs).
+fn detect_frame_rate(path: &str, stream: &serde_json::Value) -> (f64, bool) {
+    let real = parse_frame_rate(stream["r_frame_rate"].as_str().unwrap_or("30/1"));
+    // "0/0" when ffprobe couldn't work out an average
+    let average = stream["avg_frame_rate"]
+        .as_str()
+        .filter(|rate| *rate != "0/0")
+        .map(parse_frame_rate);
+
+    let variable = match average {
+        Some(average) if (real - average).abs() > average * MAX_RATE_SPREAD => true,
+        // Matching rates are the usual constant frame rate file; only sample
+        // timestamps when the rates are close but not equal, or unknown
+        Some(average) if real == average => false,
+        _ => probe_frame_timestamps(path)
+            .map(|timestamps| has_irregular_intervals(&timestamps))
+            .unwrap_or(false),
+    };
+    let average = average.unwrap_or(real);
+    (if variable { average } else { real }, variable)
+}
+
+/// Presentation times of the first video packets
+fn probe_frame_timestamps(path: &str) -> Result<Vec<f64>, VideoError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            &format!("%+{}", TIMESTAMP_SAMPLE_SEC),
+            "-show_entries",
+            "packet=pts_time",
+            "-of",
+            "csv=p=0",
+            path,
+        ])
+        .output()
+        .map_err(|e| VideoError {
+            message: format!("FFprobe execution error: {}", e),
+        })?;
+
+    if !output.status.success() {
+        return Err(VideoError {
+            message: "FFprobe timestamp probe failed".to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse().ok())
+        .collect())
+}
+
+/// Whether frames are unevenly spaced: too many intervals stray from the median
+pub fn has_irregular_intervals(timestamps: &[f64]) -> bool {
+    // Packets come in decode order, so B-frames need sorting back
+    let mut sorted = timestamps.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mut intervals: Vec<f64> = sorted.windows(2).map(|w| w[1] - w[0]).collect();
+    if intervals.len() < 10 {
+        return false;
+    }
+
+    intervals.sort_by(|a, b| a.total_cmp(b));
+    let median = intervals[intervals.len() / 2];
+    if median <= 0.0 {
+        return false;
+    }
+    let irregular = intervals
+        .iter()
+        .filter(|i| (*i - median).abs() > median * MAX_INTERVAL_JITTER)
+        .count();
+    irregular as f64 > intervals.len() as f64 * MAX_IRREGULAR_SHARE
+}
+
 fn parse_frame_rate(rate_str: &str) -> f64 {
     let parts: Vec<&str> = rate_str.split('/').collect();
     if parts.len() == 2 {
@@ -310,6 +585,15 @@ fn parse_frame_rate(rate_str: &str) -> f64 {
     30.0 // Default
 }
 
+/// Container duration, when the file has one
+fn probe_format_duration(path: &str) -> Option<f64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0", path])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
 /// Probe duration using packet counting (for WebM files without duration metadata)
 fn probe_duration_with_packets(path: &str) -> Result<f64, VideoError> {
     println!("🔍 Probing accurate duration for: {}", path);
@@ -364,164 +648,3 @@ fn probe_duration_with_packets(path: &str) -> Result<f64, VideoError> {
         message: "Could not determine duration from packets".to_string(),
     })
 }
-
-/// Export video with given configuration
-pub fn export_video(config: ExportConfig) -> Result<(), VideoError> {
-    println!("🎬 Starting video export to: {}", config.output_path);
-
-    // Check if ffmpeg is available
-    let ffmpeg_check = Command::new("ffmpeg").arg("-version").output();
-
-    if ffmpeg_check.is_err() {
-        return Err(VideoError {
-            message: "FFmpeg not found. Please install FFmpeg to export videos.".to_string(),
-        });
-    }
-
-    if config.clips.is_empty() {
-        return Err(VideoError {
-            message: "No clips to export".to_string(),
-        });
-    }
-
-    // For single clip, use simple ffmpeg command
-    if config.clips.len() == 1 {
-        export_single_clip(&config.clips[0], &config.output_path)?;
-    } else {
-        // For multiple clips, use concat
-        export_multiple_clips(&config.clips, &config.output_path)?;
-    }
-
-    println!("✅ Export complete: {}", config.output_path);
-    Ok(())
-}
-
-fn export_single_clip(clip: &ClipConfig, output_path: &str) -> Result<(), VideoError> {
-    println!("📹 Exporting single clip from: {}", clip.source_file);
-
-    let mut args = vec![
-        "-y".to_string(), // Overwrite output file
-        "-i".to_string(),
-        clip.source_file.clone(),
-    ];
-
-    // Add trim parameters if needed
-    if clip.trim_start > 0.0 || clip.trim_end < clip.duration {
-        args.push("-ss".to_string());
-        args.push(clip.trim_start.to_string());
-
-        let trimmed_duration = clip.trim_end - clip.trim_start;
-        args.push("-t".to_string());
-        args.push(trimmed_duration.to_string());
-    }
-
-    // Output settings
-    args.push("-c:v".to_string());
-    args.push("libx264".to_string());
-    args.push("-preset".to_string());
-    args.push("fast".to_string());
-    args.push("-crf".to_string());
-    args.push("23".to_string());
-    args.push("-c:a".to_string());
-    args.push("aac".to_string());
-    args.push(output_path.to_string());
-
-    println!("🔧 FFmpeg command: ffmpeg {}", args.join(" "));
-
-    let output = Command::new("ffmpeg")
-        .args(&args)
-        .output()
-        .map_err(|e| VideoError {
-            message: format!("FFmpeg execution failed: {}", e),
-        })?;
-
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(VideoError {
-            message: format!("FFmpeg export failed: {}", error_msg),
-        });
-    }
-
-    Ok(())
-}
-
-fn export_multiple_clips(clips: &[ClipConfig], output_path: &str) -> Result<(), VideoError> {
-    println!("📹 Exporting {} clips", clips.len());
-
-    // Create a temporary directory for intermediate files
-    let temp_dir = std::env::temp_dir().join("clipforge_export");
-    std::fs::create_dir_all(&temp_dir).map_err(|e| VideoError {
-        message: format!("Failed to create temp directory: {}", e),
-    })?;
-
-    // Process each clip and create intermediate files
-    let mut processed_files = Vec::new();
-    for (i, clip) in clips.iter().enumerate() {
-        let temp_file = temp_dir.join(format!("clip_{}.mp4", i));
-        export_single_clip(clip, temp_file.to_str().unwrap())?;
-        processed_files.push(temp_file);
-    }
-
-    // Create concat file list
-    let concat_file = temp_dir.join("concat_list.txt");
-    let mut concat_content = String::new();
-    for file in &processed_files {
-        concat_content.push_str(&format!("file '{}'\n", file.display()));
-    }
-    std::fs::write(&concat_file, concat_content).map_err(|e| VideoError {
-        message: format!("Failed to write concat file: {}", e),
-    })?;
-
-    // Concatenate all clips
-    let args = vec![
-        "-y",
-        "-f",
-        "concat",
-        "-safe",
-        "0",
-        "-i",
-        concat_file.to_str().unwrap(),
-        "-c",
-        "copy",
-        output_path,
-    ];
-
-    println!("🔧 FFmpeg concat command: ffmpeg {}", args.join(" "));
-
-    let output = Command::new("ffmpeg")
-        .args(&args)
-        .output()
-        .map_err(|e| VideoError {
-            message: format!("FFmpeg concat failed: {}", e),
-        })?;
-
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(VideoError {
-            message: format!("FFmpeg concat failed: {}", error_msg),
-        });
-    }
-
-    // Clean up temp files
-    for file in processed_files {
-        let _ = std::fs::remove_file(file);
-    }
-    let _ = std::fs::remove_file(concat_file);
-
-    Ok(())
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExportConfig {
-    pub clips: Vec<ClipConfig>,
-    pub output_path: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClipConfig {
-    pub source_file: String,
-    pub start_time: f64,
-    pub duration: f64,
-    pub trim_start: f64,
-    pub trim_end: f64,
-}
@@ -1,7 +1,55 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { useAppStore } from '../stores/appStore';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { save } from '@tauri-apps/plugin-dialog';
+import { TranscriptSegment } from '../types';
+
+interface ExportProgressEvent {
+  job_id: string;
+  status: 'running' | 'completed' | 'failed' | 'cancelled';
+  message: string;
+  stage: string;
+  percent: number;
+  eta_sec: number | null;
+  speed: number | null;
+  fps: number | null;
+}
+
+const formatEta = (seconds: number) => {
+  const mins = Math.floor(seconds / 60);
+  const secs = Math.round(seconds % 60);
+  return mins > 0 ? `${mins}m ${secs}s` : `${secs}s`;
+};
+
+interface ExportSettings {
+  container: 'mp4' | 'mov' | 'mkv' | 'webm';
+  video_codec: 'h264' | 'h265' | 'vp9' | 'av1';
+  [key: string]: unknown;
+}
+
+interface ExportPreset {
+  id: string;
+  name: string;
+  description: string;
+  settings: ExportSettings;
+}
+
+interface CacheInfo {
+  path: string;
+  entries: number;
+  total_bytes: number;
+}
+
+type ExportAs = 'video' | 'gif' | 'webp' | 'mp3' | 'm4a' | 'wav' | 'flac' | 'opus';
+
+const AUDIO_FORMATS: ExportAs[] = ['mp3', 'm4a', 'wav', 'flac', 'opus'];
+
+const formatBytes = (bytes: number) => {
+  if (bytes >= 1024 ** 3) return `${(bytes / 1024 ** 3).toFixed(1)} GB`;
+  if (bytes >= 1024 ** 2) return `${(bytes / 1024 ** 2).toFixed(1)} MB`;
+  return `${Math.round(bytes / 1024)} KB`;
+};
 
 interface ExportDialogProps {
   isOpen: boolean;
@@ -9,10 +57,62 @@ interface ExportDialogProps {
 }
 
 export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) => {
-  const { timeline, mediaLibrary } = useAppStore();
+  const { timeline, mediaLibrary, textOverlays, videoTracks, audioTracks } = useAppStore();
   const [isExporting, setIsExporting] = useState(false);
   const [exportProgress, setExportProgress] = useState('');
   const [exportError, setExportError] = useState('');
+  const [exportPercent, setExportPercent] = useState<number | null>(null);
+  const [exportJobId, setExportJobId] = useState<string | null>(null);
+  const [presets, setPresets] = useState<ExportPreset[]>([]);
+  const [presetId, setPresetId] = useState('');
+  const [smartRender, setSmartRender] = useState(false);
+  const [useCache, setUseCache] = useState(false);
+  const [cacheInfo, setCacheInfo] = useState<CacheInfo | null>(null);
+  const [burnInCaptions, setBurnInCaptions] = useState(false);
+  const [softCaptions, setSoftCaptions] = useState(false);
+  const [captionLanguage, setCaptionLanguage] = useState('eng');
+  const [exportAs, setExportAs] = useState<ExportAs>('video');
+  const [animatedWidth, setAnimatedWidth] = useState(640);
+  const [audioBitrate, setAudioBitrate] = useState(192);
+  const [audioTitle, setAudioTitle] = useState('');
+  const [audioArtist, setAudioArtist] = useState('');
+  const [loudnessLufs, setLoudnessLufs] = useState<number | null>(null);
+  // Index of the audio track to duck under speech, and the track that speaks (null for the clips' audio)
+  const [duckTrack, setDuckTrack] = useState<number | null>(null);
+  const [duckVoiceTrack, setDuckVoiceTrack] = useState<number | null>(null);
+
+  useEffect(() => {
+    if (!isOpen || presets.length > 0) return;
+    invoke<ExportPreset[]>('list_export_presets')
+      .then((list) => {
+        setPresets(list);
+        if (list.length > 0) setPresetId(list[0].id);
+      })
+      .catch((error) => console.error('❌ Failed to load export presets:', error));
+  }, [isOpen, presets.length]);
+
+  useEffect(() => {
+    if (!isOpen) return;
+    invoke<CacheInfo>('get_export_cache_info')
+      .then(setCacheInfo)
+      .catch((error) => console.error('❌ Failed to read export cache:', error));
+  }, [isOpen, isExporting]);
+
+  // Transcripts of the media used on the timeline, keyed by source path
+  const transcripts: Record<string, TranscriptSegment[]> = {};
+  timeline.forEach(clip => {
+    const media = mediaLibrary.find(m => m.id === clip.mediaId);
+    if (media?.transcriptSegments?.length) {
+      transcripts[media.path] = media.transcriptSegments;
+    }
+  });
+  const hasTranscripts = Object.keys(transcripts).length > 0;
+
+  const selectedPreset = presets.find(p => p.id === presetId);
+  const isAnimated = exportAs === 'gif' || exportAs === 'webp';
+  const isAudio = AUDIO_FORMATS.includes(exportAs);
+  const isLossless = exportAs === 'wav' || exportAs === 'flac';
+  const extension = exportAs === 'video' ? selectedPreset?.settings.container ?? 'mp4' : exportAs;
 
   if (!isOpen) return null;
 
@@ -23,14 +123,14 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
     }
 
     try {
-      // Open save dialog with MP4 filter
+      // Open save dialog filtered to the preset's container
       const filePath = await save({
         filters: [{
-          name: 'Video',
-          extensions: ['mp4']
+          name: isAudio ? 'Audio' : isAnimated ? 'Animation' : 'Video',
+          extensions: [extension]
         }],
-        defaultPath: 'export.mp4',
-        title: 'Export Video as MP4'
+        defaultPath: `export.${extension}`,
+        title: `Export Video as ${extension.toUpperCase()}`
       });
 
       if (!filePath) {
@@ -38,8 +138,8 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
         return;
       }
 
-      // Ensure .mp4 extension
-      const outputPath = filePath.endsWith('.mp4') ? filePath : `${filePath}.mp4`;
+      // Ensure the container's extension
+      const outputPath = filePath.endsWith(`.${extension}`) ? filePath : `${filePath}.${extension}`;
 
       console.log('🎬 Starting export to:', outputPath);
       setIsExporting(true);
@@ -56,20 +156,145 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
         return {
           source_path: media.path,
           in_sec: clip.inSec,
-          out_sec: clip.outSec
+          out_sec: clip.outSec,
+          transition: clip.transition ?? null,
+          speed: clip.speed ?? 1,
+          reverse: clip.reverse ?? false,
+          freeze: clip.freezeSec ? { hold_sec: clip.freezeSec } : null,
+          transform: clip.transform ?? null,
+          volume: clip.volume ?? null,
+          cleanup: clip.audioCleanup ?? null
         };
       });
 
+      const videoTracksData = videoTracks.map(track => ({
+        z_index: track.zIndex,
+        clips: track.clips.map(clip => {
+          const media = mediaLibrary.find(m => m.id === clip.mediaId);
+          if (!media) {
+            throw new Error(`Media not found for overlay clip ${clip.id}`);
+          }
+          return {
+            source_path: media.path,
+            in_sec: clip.inSec,
+            out_sec: clip.outSec,
+            start_sec: clip.startTimeSec,
+            rect: clip.rect,
+            opacity: clip.opacity
+          };
+        })
+      }));
+
+      const audioTracksData = audioTracks.map(track => ({
+        name: track.name,
+        gain_db: track.gainDb,
+        mute: track.mute,
+        solo: track.solo,
+        clips: track.clips.map(clip => {
+          const media = mediaLibrary.find(m => m.id === clip.mediaId);
+          if (!media) {
+            throw new Error(`Media not found for audio clip ${clip.id}`);
+          }
+          return {
+            source_path: media.path,
+            in_sec: clip.inSec,
+            out_sec: clip.outSec,
+            start_sec: clip.startTimeSec,
+            gain_db: clip.gainDb,
+            fade_in_sec: clip.fadeInSec,
+            fade_out_sec: clip.fadeOutSec,
+            volume: clip.volume ?? null
+          };
+        })
+      }));
+
       console.log('📦 Exporting clips:', clipsData);
-      setExportProgress(`Exporting ${timeline.length} clip(s) to MP4...`);
+      setExportProgress(`Exporting ${timeline.length} clip(s) to ${extension.toUpperCase()}...`);
 
-      // Call Tauri backend
-      const result = await invoke<string>('export_video', {
-        clips: clipsData,
-        outputPath: outputPath
+      // Events can arrive before invoke resolves, so buffer them until the job id is known
+      let jobId: string | null = null;
+      const pending: ExportProgressEvent[] = [];
+      let settle: (event: ExportProgressEvent) => void = () => {};
+      const finished = new Promise<ExportProgressEvent>((resolve) => { settle = resolve; });
+
+      const handleEvent = (payload: ExportProgressEvent) => {
+        if (payload.job_id !== jobId) return;
+        if (payload.status !== 'running') {
+          settle(payload);
+          return;
+        }
+        setExportPercent(payload.percent);
+        setExportProgress(
+          payload.eta_sec !== null
+            ? `${payload.stage} — about ${formatEta(payload.eta_sec)} left`
+            : payload.stage
+        );
+      };
+
+      const unlisten = await listen<ExportProgressEvent>('export-progress', (event) => {
+        if (jobId === null) {
+          pending.push(event.payload);
+        } else {
+          handleEvent(event.payload);
+        }
       });
 
-      console.log('✅ Export result:', result);
+      let result: ExportProgressEvent;
+      try {
+        // Call Tauri backend; the export runs as a background job
+        jobId = await invoke<string>('export_video', {
+          request: {
+            clips: clipsData,
+            output_path: outputPath,
+            settings: {
+              ...selectedPreset?.settings,
+              // Smart render copies the source video at its own size and rate
+              ...(smartRender && exportAs === 'video' ? { resolution: null, fps: null } : {}),
+              loudness: loudnessLufs !== null && !isAnimated ? { integrated_lufs: loudnessLufs } : null
+            },
+            mode: smartRender && exportAs === 'video' ? 'smart_render' : 'reencode',
+            use_cache: useCache,
+            text_overlays: isAudio ? [] : textOverlays,
+            video_tracks: isAudio ? [] : videoTracksData,
+            audio_tracks: isAnimated ? [] : audioTracksData,
+            ducking: duckTrack !== null && duckTrack < audioTracks.length && !isAnimated
+              ? { music_track: duckTrack, voice_track: duckVoiceTrack }
+              : null,
+            burn_in_subtitles: burnInCaptions && hasTranscripts && !isAudio ? { transcripts } : null,
+            subtitle_tracks: softCaptions && hasTranscripts && !isAnimated && !isAudio
+              ? [{ transcripts, language: captionLanguage, default: true }]
+              : [],
+            target: isAnimated
+              ? { type: 'animated', format: exportAs, max_width: animatedWidth }
+              : isAudio
+                ? {
+                    type: 'audio',
+                    format: exportAs,
+                    bitrate_kbps: audioBitrate,
+                    metadata: { title: audioTitle || null, artist: audioArtist || null }
+                  }
+                : { type: 'video' }
+          }
+        });
+        setExportJobId(jobId);
+        pending.forEach(handleEvent);
+        result = await finished;
+      } finally {
+        unlisten();
+        setExportJobId(null);
+      }
+
+      if (result.status === 'cancelled') {
+        setIsExporting(false);
+        setExportProgress('');
+        setExportPercent(null);
+        return;
+      }
+      if (result.status === 'failed') {
+        throw new Error(result.message);
+      }
+
+      console.log('✅ Export result:', result.message);
       setExportProgress('Export completed successfully!');
       
       // Close dialog after a short delay
@@ -77,6 +302,7 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
         onClose();
         setIsExporting(false);
         setExportProgress('');
+        setExportPercent(null);
       }, 2000);
 
     } catch (error) {
@@ -84,11 +310,33 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
       setExportError(error instanceof Error ? error.message : String(error));
       setIsExporting(false);
       setExportProgress('');
+      setExportPercent(null);
+    }
+  };
+
+  const handleCancelExport = async () => {
+    if (!exportJobId) return;
+    try {
+      await invoke('cancel_export', { jobId: exportJobId });
+    } catch (error) {
+      console.error('❌ Cancel export error:', error);
     }
   };
 
-  const totalDuration = timeline.reduce((sum, clip) => {
-    return sum + (clip.outSec - clip.inSec);
+  const handlePurgeCache = async () => {
+    try {
+      await invoke<CacheInfo>('purge_export_cache');
+      setCacheInfo(await invoke<CacheInfo>('get_export_cache_info'));
+    } catch (error) {
+      console.error('❌ Purge cache error:', error);
+    }
+  };
+
+  // Transitions overlap neighbouring clips; speed changes and freezes change clip lengths
+  const totalDuration = timeline.reduce((sum, clip, i) => {
+    const overlap = i < timeline.length - 1 ? clip.transition?.duration_sec ?? 0 : 0;
+    const length = clip.freezeSec ?? (clip.outSec - clip.inSec) / (clip.speed ?? 1);
+    return sum + length - overlap;
   }, 0);
 
   return (
@@ -101,7 +349,7 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
               <path fillRule="evenodd" d="M6 2a2 2 0 00-2 2v12a2 2 0 002 2h8a2 2 0 002-2V7.414A2 2 0 0015.414 6L12 2.586A2 2 0 0010.586 2H6zm5 6a1 1 0 10-2 0v3.586l-1.293-1.293a1 1 0 10-1.414 1.414l3 3a1 1 0 001.414 0l3-3a1 1 0 00-1.414-1.414L11 11.586V8z" clipRule="evenodd" />
             </svg>
           </div>
-          <h2 className="text-xl font-bold text-white">Export to MP4</h2>
+          <h2 className="text-xl font-bold text-white">Export Video</h2>
         </div>
 
         {/* Content */}
@@ -121,13 +369,205 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
                 </div>
                 <div className="flex justify-between items-center">
                   <span className="text-gray-400">Output Format:</span>
-                  <span className="text-green-400 font-mono font-bold">MP4 (H.264/AAC)</span>
+                  <select
+                    value={presetId}
+                    onChange={(e) => setPresetId(e.target.value)}
+                    disabled={isExporting || presets.length === 0}
+                    className="bg-gray-800 text-green-400 font-mono font-bold rounded px-2 py-1 border border-gray-600"
+                  >
+                    {presets.length === 0 && <option value="">MP4 (H.264/AAC)</option>}
+                    {presets.map(preset => (
+                      <option key={preset.id} value={preset.id} title={preset.description}>
+                        {preset.name}
+                      </option>
+                    ))}
+                  </select>
+                </div>
+                <div className="flex justify-between items-center mt-2">
+                  <span className="text-gray-400">Export As:</span>
+                  <div className="flex items-center gap-2">
+                    <select
+                      value={exportAs}
+                      onChange={(e) => setExportAs(e.target.value as ExportAs)}
+                      disabled={isExporting}
+                      className="bg-gray-800 text-white font-mono rounded px-2 py-1 border border-gray-600"
+                    >
+                      <option value="video">Video</option>
+                      <option value="gif">Animated GIF</option>
+                      <option value="webp">Animated WebP</option>
+                      <option value="mp3">Audio only (MP3)</option>
+                      <option value="m4a">Audio only (AAC/M4A)</option>
+                      <option value="wav">Audio only (WAV)</option>
+                      <option value="flac">Audio only (FLAC)</option>
+                      <option value="opus">Audio only (Opus)</option>
+                    </select>
+                    {isAudio && !isLossless && (
+                      <select
+                        value={audioBitrate}
+                        onChange={(e) => setAudioBitrate(Number(e.target.value))}
+                        disabled={isExporting}
+                        title="Audio bitrate"
+                        className="bg-gray-800 text-white font-mono rounded px-2 py-1 border border-gray-600"
+                      >
+                        {[96, 128, 192, 256, 320].map(kbps => (
+                          <option key={kbps} value={kbps}>{kbps}k</option>
+                        ))}
+                      </select>
+                    )}
+                    {isAnimated && (
+                      <input
+                        type="number"
+                        value={animatedWidth}
+                        onChange={(e) => setAnimatedWidth(Number(e.target.value))}
+                        disabled={isExporting}
+                        min={160}
+                        step={16}
+                        title="Maximum width in pixels"
+                        className="w-20 bg-gray-800 text-white font-mono rounded px-2 py-1 border border-gray-600"
+                      />
+                    )}
+                  </div>
+                </div>
+                {isAudio && (
+                  <div className="flex gap-2 mt-2">
+                    <input
+                      type="text"
+                      value={audioTitle}
+                      onChange={(e) => setAudioTitle(e.target.value)}
+                      disabled={isExporting}
+                      placeholder="Title"
+                      className="flex-1 bg-gray-800 text-white rounded px-2 py-1 border border-gray-600"
+                    />
+                    <input
+                      type="text"
+                      value={audioArtist}
+                      onChange={(e) => setAudioArtist(e.target.value)}
+                      disabled={isExporting}
+                      placeholder="Artist"
+                      className="flex-1 bg-gray-800 text-white rounded px-2 py-1 border border-gray-600"
+                    />
+                  </div>
+                )}
+              </div>
+
+              <div className="flex items-center justify-between text-gray-300 text-sm mb-4">
+                <span>Normalize loudness</span>
+                <select
+                  value={loudnessLufs ?? ''}
+                  onChange={(e) => setLoudnessLufs(e.target.value === '' ? null : Number(e.target.value))}
+                  disabled={isExporting || isAnimated}
+                  className="bg-gray-800 text-white rounded px-2 py-1 border border-gray-600"
+                >
+                  <option value="">Off</option>
+                  <option value="-16">-16 LUFS (web, podcasts)</option>
+                  <option value="-23">-23 LUFS (broadcast)</option>
+                </select>
+              </div>
+
+              {audioTracks.length > 0 && (
+                <div className="flex items-center justify-between gap-2 text-gray-300 text-sm mb-4">
+                  <span>Duck under speech</span>
+                  <select
+                    value={duckTrack ?? ''}
+                    onChange={(e) => setDuckTrack(e.target.value === '' ? null : Number(e.target.value))}
+                    disabled={isExporting || isAnimated}
+                    className="bg-gray-800 text-white rounded px-2 py-1 border border-gray-600"
+                  >
+                    <option value="">Off</option>
+                    {audioTracks.map((track, i) => (
+                      <option key={track.id} value={i}>{track.name || `Track ${i + 1}`}</option>
+                    ))}
+                  </select>
+                  <span>when</span>
+                  <select
+                    value={duckVoiceTrack ?? ''}
+                    onChange={(e) => setDuckVoiceTrack(e.target.value === '' ? null : Number(e.target.value))}
+                    disabled={isExporting || isAnimated || duckTrack === null}
+                    className="bg-gray-800 text-white rounded px-2 py-1 border border-gray-600"
+                  >
+                    <option value="">clips speak</option>
+                    {audioTracks.map((track, i) => i !== duckTrack && (
+                      <option key={track.id} value={i}>{track.name || `Track ${i + 1}`} speaks</option>
+                    ))}
+                  </select>
                 </div>
+              )}
+
+              <label className="flex items-center gap-2 text-gray-300 text-sm mb-4">
+                <input
+                  type="checkbox"
+                  checked={smartRender && exportAs === 'video'}
+                  onChange={(e) => setSmartRender(e.target.checked)}
+                  disabled={isExporting || exportAs !== 'video'}
+                />
+                Smart render (copy untouched video, re-encode only around cuts)
+              </label>
+
+              <label className="flex items-center gap-2 text-gray-300 text-sm mb-4">
+                <input
+                  type="checkbox"
+                  checked={burnInCaptions && hasTranscripts && !isAudio}
+                  onChange={(e) => setBurnInCaptions(e.target.checked)}
+                  disabled={isExporting || (smartRender && exportAs === 'video') || isAudio || !hasTranscripts}
+                />
+                Burn in captions from transcripts
+                {!hasTranscripts && <span className="text-gray-500">(transcribe a clip first)</span>}
+              </label>
+
+              <div className="flex items-center gap-2 text-gray-300 text-sm mb-4">
+                <label className="flex items-center gap-2">
+                  <input
+                    type="checkbox"
+                    checked={softCaptions && hasTranscripts && !isAnimated && !isAudio}
+                    onChange={(e) => setSoftCaptions(e.target.checked)}
+                    disabled={isExporting || !hasTranscripts || isAnimated || isAudio}
+                  />
+                  Add captions as a subtitle track
+                </label>
+                <input
+                  type="text"
+                  value={captionLanguage}
+                  onChange={(e) => setCaptionLanguage(e.target.value.toLowerCase())}
+                  disabled={isExporting || !softCaptions}
+                  maxLength={3}
+                  title="ISO 639-2 language code"
+                  className="w-14 bg-gray-800 text-white font-mono rounded px-2 py-0.5 border border-gray-600"
+                />
+              </div>
+
+              <div className="flex items-center justify-between text-gray-300 text-sm mb-4">
+                <label className="flex items-center gap-2">
+                  <input
+                    type="checkbox"
+                    checked={useCache}
+                    onChange={(e) => setUseCache(e.target.checked)}
+                    disabled={isExporting || (smartRender && exportAs === 'video')}
+                  />
+                  Reuse unchanged clips from earlier exports
+                </label>
+                {cacheInfo && cacheInfo.entries > 0 && (
+                  <button
+                    onClick={handlePurgeCache}
+                    disabled={isExporting}
+                    className="text-gray-400 hover:text-white underline"
+                    title={cacheInfo.path}
+                  >
+                    Clear cache ({formatBytes(cacheInfo.total_bytes)})
+                  </button>
+                )}
               </div>
 
               {exportProgress && (
                 <div className="bg-blue-900 bg-opacity-30 border border-blue-500 rounded p-3 mb-3">
                   <p className="text-blue-300 text-sm">{exportProgress}</p>
+                  {exportPercent !== null && (
+                    <div className="mt-2 h-2 bg-gray-700 rounded">
+                      <div
+                        className="h-2 bg-blue-500 rounded transition-all"
+                        style={{ width: `${exportPercent.toFixed(1)}%` }}
+                      />
+                    </div>
+                  )}
                 </div>
               )}
 
@@ -140,7 +580,7 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
               <p className="text-gray-400 text-sm">
                 {isExporting 
                   ? 'Please wait while your video is being processed...'
-                  : 'All clips will be trimmed and combined into a single MP4 file.'}
+                  : selectedPreset?.description ?? 'All clips will be trimmed and combined into a single MP4 file.'}
               </p>
             </>
           )}
@@ -149,15 +589,17 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
         {/* Actions */}
         <div className="flex gap-3">
           <button
-            onClick={onClose}
-            disabled={isExporting}
+            onClick={isExporting ? handleCancelExport : onClose}
+            disabled={isExporting && !exportJobId}
             className={`flex-1 px-4 py-2 rounded-md transition-colors font-medium ${
-              isExporting 
+              isExporting && !exportJobId
                 ? 'bg-gray-700 text-gray-500 cursor-not-allowed'
-                : 'bg-gray-600 hover:bg-gray-700 text-white'
+                : isExporting
+                  ? 'bg-red-600 hover:bg-red-700 text-white'
+                  : 'bg-gray-600 hover:bg-gray-700 text-white'
             }`}
           >
-            {isExporting ? 'Exporting...' : 'Cancel'}
+            {isExporting ? 'Cancel Export' : 'Cancel'}
           </button>
           <button
             onClick={handleExport}
@@ -177,7 +619,7 @@ export const ExportDialog: React.FC<ExportDialogProps> = ({ isOpen, onClose }) =
                 Exporting...
               </span>
             ) : (
-              'Export MP4'
+              `Export ${extension.toUpperCase()}`
             )}
           </button>
         </div>
@@ -4,6 +4,7 @@ import { open } from '@tauri-apps/plugin-dialog';
 import { convertFileSrc } from '@tauri-apps/api/core';
 import { invoke } from '@tauri-apps/api/core';
 import { TranscriptDialog } from './TranscriptDialog';
+import { Transcript } from '../types';
 
 export const MediaLibrary: React.FC = () => {
   const { mediaLibrary, addMediaFile, updateMediaFile } = useAppStore();
@@ -110,7 +111,7 @@ export const MediaLibrary: React.FC = () => {
 
       console.log('🎤 Starting transcription for:', fileName);
       
-      const transcript = await invoke<string>('transcribe_video', {
+      const { text: transcript, segments } = await invoke<Transcript>('transcribe_video', {
         videoPath: filePath,
       });
 
@@ -119,6 +120,7 @@ export const MediaLibrary: React.FC = () => {
       // Save transcript to media file
       updateMediaFile(fileId, { 
         transcript, 
+        transcriptSegments: segments,
         transcriptLoading: false 
       });
 
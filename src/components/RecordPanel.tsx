@@ -222,10 +222,32 @@ export const RecordPanel: React.FC = () => {
     }
   };
 
+  // Convert a variable frame rate file in a background job and import the proxy
+  const conformToCfr = async (path: string) => {
+    const jobId = await invoke<string>('conform_to_cfr', { path });
+    for (;;) {
+      const status = await invoke<any>('get_export_status', { jobId });
+      if (status.state === 'completed') {
+        return invoke<any>('import_video', { path: status.output_path });
+      }
+      if (status.state !== 'running') {
+        throw new Error(status.error ?? 'Frame rate conversion was cancelled');
+      }
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+  };
+
   // Import recorded file to media library
   const importRecordedFile = async (filePath: string) => {
     try {
-      const metadata = await invoke<any>('import_video', { path: filePath });
+      let metadata = await invoke<any>('import_video', { path: filePath });
+
+      // Recordings are often variable frame rate, which drifts out of sync and trims badly
+      if (metadata.variable_frame_rate && window.confirm(
+        'This recording has a variable frame rate, which can put audio out of sync and make trims land on the wrong frame. Convert it to a constant frame rate copy?'
+      )) {
+        metadata = await conformToCfr(metadata.path);
+      }
       
       const mediaFile: MediaFile = {
         id: Math.random().toString(36).substr(2, 9),
@@ -236,6 +258,7 @@ export const RecordPanel: React.FC = () => {
         width: metadata.width,
         height: metadata.height,
         sizeBytes: metadata.size,
+        variableFrameRate: metadata.variable_frame_rate,
       };
 
       addMediaFile(mediaFile);
@@ -0,0 +1,46 @@
+import { useState, useCallback } from 'react';
+import { invoke, convertFileSrc } from '@tauri-apps/api/core';
+import { AudioCleanup } from '../types';
+
+export interface CleanupPreview {
+  path: string;
+  start_sec: number;    // play the source from here for the "before" side
+  duration_sec: number;
+}
+
+// Renders a 10-second cleaned sample of a clip for A/B listening before export
+export const useCleanupPreview = () => {
+  const [preview, setPreview] = useState<CleanupPreview | null>(null);
+  const [previewUrl, setPreviewUrl] = useState<string | null>(null);
+  const [isRendering, setIsRendering] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const renderPreview = useCallback(async (
+    sourcePath: string,
+    inSec: number,
+    outSec: number,
+    cleanup: AudioCleanup,
+    startSec?: number
+  ) => {
+    setIsRendering(true);
+    setError(null);
+    try {
+      const result = await invoke<CleanupPreview>('preview_audio_cleanup', {
+        clip: { source_path: sourcePath, in_sec: inSec, out_sec: outSec, cleanup },
+        startSec: startSec ?? null
+      });
+      console.log('🎧 Cleanup preview rendered:', result.path);
+      setPreview(result);
+      setPreviewUrl(convertFileSrc(result.path));
+      return result;
+    } catch (err) {
+      console.error('Failed to render cleanup preview:', err);
+      setError(String(err));
+      return null;
+    } finally {
+      setIsRendering(false);
+    }
+  }, []);
+
+  return { preview, previewUrl, isRendering, error, renderPreview };
+};
@@ -1,9 +1,12 @@
 import { create } from 'zustand';
-import { AppState, MediaFile, TimelineClip, PendingTrim, RecordingMode, ScreenSource, PiPPosition } from '../types';
+import { AppState, MediaFile, TimelineClip, TextOverlay, VideoTrack, AudioTrack, PendingTrim, RecordingMode, ScreenSource, PiPPosition } from '../types';
 
 export const useAppStore = create<AppState>((set, get) => ({
   mediaLibrary: [],
   timeline: [],
+  textOverlays: [],
+  videoTracks: [],
+  audioTracks: [],
   selectedClip: null,
   playheadPosition: 0,
   isPlaying: false,
@@ -181,6 +184,66 @@ export const useAppStore = create<AppState>((set, get) => ({
     console.log('✅ Clip removed from timeline');
   },
   
+  addTextOverlay: (overlay: TextOverlay) => {
+    set((state) => ({
+      textOverlays: [...state.textOverlays, overlay]
+    }));
+  },
+
+  updateTextOverlay: (overlayId: string, updates: Partial<TextOverlay>) => {
+    set((state) => ({
+      textOverlays: state.textOverlays.map(overlay =>
+        overlay.id === overlayId ? { ...overlay, ...updates } : overlay
+      )
+    }));
+  },
+
+  removeTextOverlay: (overlayId: string) => {
+    set((state) => ({
+      textOverlays: state.textOverlays.filter(overlay => overlay.id !== overlayId)
+    }));
+  },
+
+  addVideoTrack: (track: VideoTrack) => {
+    set((state) => ({
+      videoTracks: [...state.videoTracks, track]
+    }));
+  },
+
+  updateVideoTrack: (trackId: string, updates: Partial<VideoTrack>) => {
+    set((state) => ({
+      videoTracks: state.videoTracks.map(track =>
+        track.id === trackId ? { ...track, ...updates } : track
+      )
+    }));
+  },
+
+  removeVideoTrack: (trackId: string) => {
+    set((state) => ({
+      videoTracks: state.videoTracks.filter(track => track.id !== trackId)
+    }));
+  },
+
+  addAudioTrack: (track: AudioTrack) => {
+    set((state) => ({
+      audioTracks: [...state.audioTracks, track]
+    }));
+  },
+
+  updateAudioTrack: (trackId: string, updates: Partial<AudioTrack>) => {
+    set((state) => ({
+      audioTracks: state.audioTracks.map(track =>
+        track.id === trackId ? { ...track, ...updates } : track
+      )
+    }));
+  },
+
+  removeAudioTrack: (trackId: string) => {
+    set((state) => ({
+      audioTracks: state.audioTracks.filter(track => track.id !== trackId)
+    }));
+  },
+  
   // Recording actions
   setIsRecording: (recording: boolean) => {
     set({ isRecording: recording });
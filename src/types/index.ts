@@ -7,10 +7,23 @@ export type MediaFile = {
   width: number;
   height: number;
   sizeBytes: number;
+  variableFrameRate?: boolean; // Uneven frame timing; exports conform it to a constant rate
   transcript?: string; // AI-generated transcript
+  transcriptSegments?: TranscriptSegment[]; // Timed transcript, in source time
   transcriptLoading?: boolean; // Loading state for transcript generation
 };
 
+export type TranscriptSegment = {
+  start_sec: number;
+  end_sec: number;
+  text: string;
+};
+
+export type Transcript = {
+  text: string;
+  segments: TranscriptSegment[];
+};
+
 export type ScreenSource = {
   id: string;
   name: string;
@@ -31,12 +44,104 @@ export type PiPPosition = {
   height: number;
 };
 
+export type ClipTransition = {
+  kind: 'crossfade' | 'dip_to_black' | 'dip_to_white' | 'wipe' | 'slide';
+  duration_sec: number;
+  direction?: 'left' | 'right' | 'up' | 'down';
+};
+
+export type ClipTransform = {
+  crop?: { x: number; y: number; width: number; height: number };
+  rotation_deg?: number;  // clockwise
+  flip_horizontal?: boolean;
+  flip_vertical?: boolean;
+  scale_mode?: 'fit' | 'fill' | 'stretch';
+  pad_color?: string;
+};
+
+// Keyframe times are in source seconds, so they stay put when the clip is trimmed or retimed
+export type VolumeEnvelope = {
+  keyframes: { time_sec: number; gain_db: number }[];
+  interpolation?: 'linear' | 'smooth';
+};
+
+// Fixes for noisy mic recordings, applied at export; leave a field out to skip it
+export type AudioCleanup = {
+  denoise_db?: number;   // 0 is off, ~12 for fan noise
+  highpass_hz?: number;
+  lowpass_hz?: number;
+  hum_hz?: 50 | 60;      // mains frequency
+  deess?: number;        // 0-1
+};
+
 export type TimelineClip = {
   id: string;
   mediaId: string;
   startTimeSec: number; // position on timeline
   inSec: number;        // source start time
   outSec: number;       // source end time
+  transition?: ClipTransition; // into the next clip
+  speed?: number;       // playback speed, 0.25-16 (default 1)
+  reverse?: boolean;    // play backwards
+  freezeSec?: number;   // hold the frame at inSec for this long instead of playing
+  transform?: ClipTransform;
+  volume?: VolumeEnvelope;
+  audioCleanup?: AudioCleanup;
+};
+
+export type OverlayClip = {
+  id: string;
+  mediaId: string;
+  startTimeSec: number; // position on timeline
+  inSec: number;
+  outSec: number;
+  rect: { x: number; y: number; width: number; height: number }; // fractions of the output frame
+  opacity: number;      // 0-1
+};
+
+// A video track drawn over the main timeline, e.g. a webcam picture-in-picture
+export type VideoTrack = {
+  id: string;
+  zIndex: number;       // higher tracks are drawn on top
+  clips: OverlayClip[];
+};
+
+export type AudioClip = {
+  id: string;
+  mediaId: string;
+  startTimeSec: number; // position on timeline
+  inSec: number;
+  outSec: number;
+  gainDb: number;
+  fadeInSec: number;
+  fadeOutSec: number;
+  volume?: VolumeEnvelope;
+};
+
+// Music bed, voiceover or sound effects mixed with the clips' own audio
+export type AudioTrack = {
+  id: string;
+  name: string;
+  clips: AudioClip[];
+  gainDb: number;
+  mute: boolean;
+  solo: boolean;
+};
+
+export type TextOverlay = {
+  id: string;
+  text: string;
+  font_file?: string;
+  font_size: number;
+  color: string;
+  background?: { color: string; opacity: number; padding: number };
+  position:
+    | { type: 'top' | 'center' | 'bottom' | 'lower_third' }
+    | { type: 'custom'; x: number; y: number };
+  start_sec: number; // output timeline time
+  end_sec: number;
+  fade_in_sec: number;
+  fade_out_sec: number;
 };
 
 export type PendingTrim = {
@@ -51,6 +156,9 @@ export type PendingTrim = {
 export interface AppState {
   mediaLibrary: MediaFile[];
   timeline: TimelineClip[];
+  textOverlays: TextOverlay[];
+  videoTracks: VideoTrack[];
+  audioTracks: AudioTrack[];
   selectedClip: string | null;
   playheadPosition: number;
   isPlaying: boolean;
@@ -87,6 +195,15 @@ export interface AppState {
   cancelTrim: () => void;
   reorderTimeline: () => void;
   removeTimelineClip: (clipId: string) => void;
+  addTextOverlay: (overlay: TextOverlay) => void;
+  updateTextOverlay: (overlayId: string, updates: Partial<TextOverlay>) => void;
+  removeTextOverlay: (overlayId: string) => void;
+  addVideoTrack: (track: VideoTrack) => void;
+  updateVideoTrack: (trackId: string, updates: Partial<VideoTrack>) => void;
+  removeVideoTrack: (trackId: string) => void;
+  addAudioTrack: (track: AudioTrack) => void;
+  updateAudioTrack: (trackId: string, updates: Partial<AudioTrack>) => void;
+  removeAudioTrack: (trackId: string) => void;
   
   // Recording actions
   setIsRecording: (recording: boolean) => void;